@@ -1,21 +1,43 @@
 use crate::{
     config::{
         structs::Config,
-        utils::{convert_to_string_if_some, parse_request},
+        utils::{convert_to_string_if_some, expand_env_variables, parse_request},
+    },
+    network::{
+        auth::{DigestAuth, Netrc, TokenRefresher},
+        block::BlockDetector,
+        har::HarWriter,
+        http_log::HttpLogger,
+        limiter::{HostLimiter, RateLimiter},
+        signer::{AwsSigV4, RequestSigner},
+        utils::{
+            ascii_set, ca_certificates, client_identity, replace_file_templates, BinaryMimes,
+            DataType, EncodeMode, ErrorClass, Headers,
+        },
+        warc::WarcWriter,
+    },
+    runner::{
+        checkpoint::Checkpoint,
+        detector::{parse_detector, Detector, MatchDetector, RegexDetector},
+        utils::ReasonKind,
     },
-    network::utils::{DataType, Headers},
 };
 use clap::{crate_version, App, AppSettings, Arg};
+use parking_lot::Mutex;
+use regex::Regex;
 use std::{
     collections::HashMap,
     error::Error,
     fs,
     io::{self, Write},
+    sync::Arc,
 };
 use tokio::time::Duration;
 use url::Url;
 
-use super::utils::{add_default_headers, mimic_browser_headers, read_urls_if_possible};
+use super::utils::{
+    add_default_headers, mimic_browser_headers, pattern_to_regex, read_urls_if_possible,
+};
 
 pub fn get_config() -> Result<Config, Box<dyn Error>> {
     let app = App::new("x8")
@@ -48,10 +70,8 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
         .arg(Arg::with_name("port")
             .long("port")
             .short("-p")
-            .help("Port to use with request file")
+            .help("Port to use with request file. With urls, requests are sent to this port while the url's one stays within the Host header")
             .takes_value(true)
-            .requires("request")
-            .conflicts_with("url")
         )
         .arg(Arg::with_name("split-by")
             .long("split-by")
@@ -64,15 +84,21 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
             Arg::with_name("wordlist")
                 .short("w")
                 .long("wordlist")
-                .help("The file with parameters (leave empty to read from stdin)")
-                .default_value("")
+                .help("The files or http(s) urls with parameters (leave empty to read from stdin)\nMultiple wordlists are merged and deduplicated: -w php.txt api.txt")
+                .takes_value(true)
+                .min_values(1),
+        )
+        .arg(
+            Arg::with_name("param-regex")
+                .long("param-regex")
+                .help("Skip parameters with names that don't match the regex (spaces, control characters, urls, ..)")
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("parameter-template")
                 .short("P")
                 .long("param-template")
-                .help("%k - key, %v - value. Example: --param-template 'user[%k]=%v'\nDefault: urlencoded - <%k=%v>, json - <\"%k\":%v>, headers - <%k=%v>")
+                .help("%k - key, %v - value. Example: --param-template 'user[%k]=%v'\nDefault: urlencoded - <%k=%v>, json - <\"%k\":%v>, delimited - <%k:%v>, headers - <%k=%v>")
                 .takes_value(true),
         )
         .arg(
@@ -86,15 +112,45 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
             Arg::with_name("body")
                 .short("b")
                 .long("body")
-                .help("Example: --body '{\"x\":{%s}}'\nAvailable variables: {{random}}")
+                .help("Example: --body '{\"x\":{%s}}'\nAvailable variables: {{random}}, {{seq}}, {{file:path}}")
                 .value_name("body")
                 .conflicts_with("request")
         )
+        .arg(
+            Arg::with_name("body-file")
+                .long("body-file")
+                .help("Read the body from the file. The same as --body, but without shell escaping")
+                .takes_value(true)
+                .conflicts_with_all(&["request", "body"])
+        )
+        .arg(
+            Arg::with_name("literal-body")
+                .long("literal-body")
+                .help("Send the body (from --body or the request file) as is: without injection points and variables.\nParameters are injected into the query or headers instead")
+        )
+        .arg(
+            Arg::with_name("query-candidates")
+                .long("query-candidates")
+                .help("When parameters are injected into the body, check the parameters from the url's query within the body as well")
+        )
+        .arg(
+            Arg::with_name("json-array-entries")
+                .long("json-array-entries")
+                .help("In case the body is a json array, add parameters as separate entries ({\"param\":value}) instead of adding them to the first object")
+        )
+        .arg(
+            Arg::with_name("json-path")
+                .long("json-path")
+                .help("Inject parameters into the nested object within the json body instead of the top level one.\nMissing objects are created. Array elements are referenced by their index\nExample: --json-path user.profile")
+                .value_name("path")
+                .takes_value(true)
+                .conflicts_with_all(&["headers-discovery", "cookie-discovery", "json-array-entries"])
+        )
         .arg(
             Arg::with_name("data-type")
                 .short("t")
                 .long("data-type")
-                .help("Available: urlencode, json\nCan be detected automatically if --body is specified (default is \"urlencode\")")
+                .help("Available: urlencode, json, delimited, raw, xml, multipart\nCan be detected automatically if --body is specified (default is \"urlencode\")\nDelimited bodies are fully defined by --param-template and --joiner (%k:%v and \\n by default)\nWith raw, only %s is replaced and Content-Type isn't sent")
                 .value_name("data-type")
         )
         .arg(
@@ -104,6 +160,29 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
                 .value_name("proxy")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("http-proxy")
+                .long("http-proxy")
+                .help("Proxy only for http:// urls. Takes precedence over -x")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("https-proxy")
+                .long("https-proxy")
+                .help("Proxy only for https:// urls. Takes precedence over -x")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("env-proxy")
+                .long("env-proxy")
+                .help("Use HTTP_PROXY, HTTPS_PROXY, ALL_PROXY and NO_PROXY environment variables when no proxy is provided")
+        )
+        .arg(
+            Arg::with_name("connect-to")
+                .long("connect-to")
+                .help("Connect to host[:port] (and send it within SNI) instead of the url's host. The url's host is sent within the Host header")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("burp-proxy")
                 .short("B")
@@ -118,6 +197,13 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
                 .default_value("0")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("delay-jitter")
+                .long("delay-jitter")
+                .help("Randomly change the delay by up to ± the value in milliseconds for every request")
+                .default_value("0")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("output")
                 .short("o")
@@ -129,10 +215,17 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
             Arg::with_name("output-format")
                 .short("O")
                 .long("output-format")
-                .help("standart, json, url, request")
+                .help("standart, json, url, request, openapi, markdown")
                 .default_value("standart")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("manifest")
+                .long("manifest")
+                .help("Write the effective configuration of every run (method, template, data type, headers, proxy, ..) to the file in json.\nSecrets within headers and proxies are redacted")
+                .value_name("file")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("append")
                 .long("append")
@@ -144,6 +237,11 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
                 .requires("output")
                 .help("Skip writing to file outputs of url:method pairs without found parameters")
         )
+        .arg(
+            Arg::with_name("merge-findings")
+                .long("merge-findings")
+                .help("Merge parameters found within the same url via different injection places (e.g. -X GET POST).\nThe output is printed at the end of the scan")
+        )
         .arg(
             Arg::with_name("method")
                 .short("X")
@@ -162,6 +260,13 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
                 .min_values(1)
                 .conflicts_with("request")
         )
+        .arg(
+            Arg::with_name("host-headers")
+                .long("host-headers")
+                .help("Headers for the specific host. Overwrite -H headers with the same name.\nExample: --host-headers 'api.example.com=Authorization: Bearer 1' 'example.com=X-Token: 2'")
+                .takes_value(true)
+                .min_values(1)
+        )
         .arg(
             Arg::with_name("invert")
                 .long("invert")
@@ -169,6 +274,12 @@ pub fn get_config() -> Result<Config, Box<dyn Error>> {
 It's possible to overwrite this behavior by specifying the option")
                 .conflicts_with("headers-discovery")
         )
+        .arg(
+            Arg::with_name("query-and-body")
+                .long("query-and-body")
+                .help("Check POST,PUT,PATCH,DELETE methods with the parameters within the query as well (GET and others - within the body with --invert).\nFindings are merged the same way as with --merge-findings")
+                .conflicts_with_all(&["headers-discovery", "fragment"])
+        )
         .arg(
             Arg::with_name("headers-discovery")
                 .long("headers")
@@ -176,6 +287,28 @@ It's possible to overwrite this behavior by specifying the option")
                 .conflicts_with("invert")
                 .conflicts_with("param-template")
         )
+        .arg(
+            Arg::with_name("cookie-discovery")
+                .long("cookie-discovery")
+                .help("Switch to cookie discovery mode: parameters are sent within the Cookie header (joined with '; ').\nUser supplied cookies are kept")
+                .conflicts_with_all(&["headers-discovery", "cookies", "invert", "fragment", "query-and-body"])
+        )
+        .arg(
+            Arg::with_name("include-headers")
+                .long("include-headers")
+                .help("Check only the headers that match one of the patterns in header discovery mode.\nExample: --include-headers 'X-*' '/^(client|true)-/'")
+                .min_values(1)
+                .requires("headers-discovery")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("exclude-headers")
+                .long("exclude-headers")
+                .help("Skip the headers that match one of the patterns in header discovery mode.\nExample: --exclude-headers 'Accept*' 'Sec-*'")
+                .min_values(1)
+                .requires("headers-discovery")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("force")
                 .long("force")
@@ -199,6 +332,11 @@ It's possible to overwrite this behavior by specifying the option")
                 .long("disable-trustdns")
                 .help("Can solve some dns related problems")
         )
+        .arg(
+            Arg::with_name("disable-http09")
+                .long("disable-http09")
+                .help("Treat HTTP/0.9 responses (without the status line) as errors instead of accepting them")
+        )
         .arg(
             Arg::with_name("disable-progress-bar")
                 .long("disable-progress-bar")
@@ -236,16 +374,40 @@ It's possible to overwrite this behavior by specifying the option")
                 .min_values(1)
                 .conflicts_with("disable-custom-parameters")
         )
+        .arg(
+            Arg::with_name("param-values")
+                .long("param-values")
+                .help("The file with values to check for specific parameters. One name=value per line, the same name can be repeated:\nrole=admin\nrole=user")
+                .takes_value(true)
+                .conflicts_with("disable-custom-parameters")
+        )
         .arg(
             Arg::with_name("follow-redirects")
                 .long("follow-redirects")
                 .short("L")
                 .help("Follow redirections")
         )
+        .arg(
+            Arg::with_name("follow-client-redirects")
+                .long("follow-client-redirects")
+                .help("Follow <meta http-equiv=\"refresh\"> and simple javascript redirects (location = '..') on 200 pages.\nThe parameters are checked on the target page")
+                .conflicts_with_all(&["no-content-length", "request-line"])
+        )
         .arg(
             Arg::with_name("encode")
                 .long("encode")
-                .help("Encodes query or body before making a request, i.e & -> %26, = -> %3D\nList of chars to encode: \", `, , <, >, &, #, ;, /, =, %")
+                .help("Encodes query or body before making a request, i.e & -> %26, = -> %3D\nList of chars to encode: \", `, , <, >, &, #, ;, /, =, %\nWith double, the query is encoded twice (= -> %253D)")
+                .value_name("single/double")
+                .min_values(0)
+                .max_values(1)
+        )
+        .arg(
+            Arg::with_name("encode-chars")
+                .long("encode-chars")
+                .help("Characters to encode with --encode instead of the default ones. Control characters are always encoded\nExample: --encode-chars '&#;/=%+()'")
+                .value_name("chars")
+                .takes_value(true)
+                .requires("encode")
         )
         .arg(
             Arg::with_name("strict")
@@ -268,9 +430,15 @@ It's possible to overwrite this behavior by specifying the option")
         .arg(
             Arg::with_name("save-responses")
                 .long("save-responses")
-                .help("Save request and response to a directory when a parameter is found")
+                .help("Save request and response to a directory when a parameter is found. A minimized request with only the found parameter is saved as well")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("save-diffs")
+                .long("save-diffs")
+                .help("Append the diff between the initial response and the found one to the saved responses")
+                .requires("save-responses")
+        )
         .arg(
             Arg::with_name("learn-requests-count")
                 .long("learn-requests")
@@ -278,6 +446,46 @@ It's possible to overwrite this behavior by specifying the option")
                 .default_value("9")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("calibrate")
+                .long("calibrate")
+                .help("Send n requests with a random parameter before the scan and suppress findings with the same code and size range (soft-404 and wildcard pages)")
+                .default_value("0")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("warm-up")
+                .long("warm-up")
+                .help("Send n requests the same as the initial one and warn in case their code, body or reflections differ")
+                .default_value("0")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("warm-up-confirm")
+                .long("warm-up-confirm")
+                .help("Confirm every found parameter at least 3 times in case the baseline is unstable (see --warm-up and --confirm)")
+        )
+        .arg(
+            Arg::with_name("baselines")
+                .long("baselines")
+                .help("Send n requests the same as the initial one and treat the distinct responses as legitimate variants of the page. Responses similar to any of them aren't reported")
+                .default_value("1")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("baseline-similarity")
+                .long("baseline-similarity")
+                .help("Min similarity (0.0-1.0) of the bodies to treat a response as one of the variants from --baselines")
+                .default_value("0.9")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("timing-report")
+                .long("timing-report")
+                .help("Print n parameters with the highest median response time after the scan, even if they weren't reported as found")
+                .default_value("0")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("recursion-depth")
                 .long("recursion-depth")
@@ -300,6 +508,57 @@ Conflicts with --verify for now.")
                 .default_value("15")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("probe-timeout")
+                .long("probe-timeout")
+                .help("Timeout in seconds for requests during the parameter discovery.\nVerification and replay requests use --timeout. 0 - --timeout is used everywhere")
+                .default_value("0")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("retry-on")
+                .long("retry-on")
+                .help("Transport errors that are retried once. HTTP error statuses are retried only with --retry-on-code.\nAvailable: timeout, connect, body, tls, other, none")
+                .default_value("timeout,connect,body")
+                .use_delimiter(true)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("cache-buster")
+                .long("cache-buster")
+                .help("Add a random query parameter to every request to avoid cached responses.\nauto - only in case the response has the Vary header or seems to be cached")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("retry-on-code")
+                .long("retry-on-code")
+                .help("Retry responses with these status codes the same way as transport errors.\nExample: --retry-on-code 502,503,504")
+                .value_name("codes")
+                .use_delimiter(true)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("retry-on-match")
+                .long("retry-on-match")
+                .help("Retry responses (headers and body) that match the regex the same way as transport errors.\nExample: --retry-on-match '\"error\":\\s*\"try again'")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("max-response-headers")
+                .long("max-response-headers")
+                .help("The max number of response headers to process, the rest are dropped. 0 - unlimited")
+                .default_value("100")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("max-response-headers-size")
+                .long("max-response-headers-size")
+                .help("The max total size of response headers in bytes, the rest are dropped. 0 - unlimited")
+                .default_value("65536")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("concurrency")
                 .short("c")
@@ -307,6 +566,20 @@ Conflicts with --verify for now.")
                 .default_value("1")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("max-host-connections")
+                .long("max-host-connections")
+                .help("The max number of simultaneous connections to a single host across all the workers. 0 - unlimited")
+                .default_value("0")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("rate-limit")
+                .long("rate-limit")
+                .help("The max number of requests per second across all the workers and urls. The delay (-d) is applied on top of it")
+                .value_name("requests per second")
+                .takes_value(true)
+        )
         .arg(
             Arg::with_name("workers")
                 .short("W")
@@ -325,6 +598,90 @@ Conflicts with --verify for now.")
                 .long("reflected-only")
                 .help("Disable page comparison and search for reflected parameters only.")
         )
+        .arg(
+            Arg::with_name("content-type")
+                .long("content-type")
+                .help("Always send this Content-Type, even if it doesn't match the body")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("disable-auto-content-type")
+                .long("disable-auto-content-type")
+                .help("Don't add Content-Type automatically. Only the headers from -H are sent")
+                .conflicts_with("content-type")
+        )
+        .arg(
+            Arg::with_name("no-content-length")
+                .long("no-content-length")
+                .help("Send requests without Content-Length and Transfer-Encoding headers.\nThe body is delimited by closing the connection. Doesn't support proxies, redirects and HTTP/2")
+                .conflicts_with_all(&["proxy", "http-proxy", "https-proxy", "http", "replay-proxy", "follow-redirects"])
+        )
+        .arg(
+            Arg::with_name("request-line")
+                .long("request-line")
+                .help("Send the exact request line. {{path}} is replaced with the path with parameters.\nExample: --request-line 'GET http://internal{{path}} HTTP/1.1'. Doesn't support proxies, redirects and HTTP/2")
+                .takes_value(true)
+                .conflicts_with_all(&["proxy", "http-proxy", "https-proxy", "http", "replay-proxy", "follow-redirects"])
+        )
+        .arg(
+            Arg::with_name("protocol-diff")
+                .long("protocol-diff")
+                .help("Send parameters over both HTTP/1.1 and HTTP/2 and report the ones that change the page differently depending on the protocol")
+                .conflicts_with_all(&["http", "no-content-length", "request-line"])
+        )
+        .arg(
+            Arg::with_name("auth-diff")
+                .long("auth-diff")
+                .help("Resend found parameters without the Cookie and Authorization headers and report the ones that change the page only when authenticated")
+        )
+        .arg(
+            Arg::with_name("tls-info")
+                .long("tls-info")
+                .help("Print the negotiated TLS version, cipher and ALPN protocol for https urls")
+                .conflicts_with_all(&["proxy", "http-proxy", "https-proxy", "env-proxy"])
+        )
+        .arg(
+            Arg::with_name("alpn")
+                .long("alpn")
+                .help("ALPN protocols to offer in the TLS handshake instead of the default ones. 'none' to not offer any.\nExample: --alpn http/1.1 or --alpn h2 bogus")
+                .takes_value(true)
+                .min_values(1)
+                .conflicts_with("protocol-diff")
+        )
+        .arg(
+            Arg::with_name("ca-bundle")
+                .long("ca-bundle")
+                .help("Verify server certificates against the CA certificates from the PEM file (may contain a few certificates).\nBy default, invalid certificates are accepted")
+                .value_name("file")
+                .takes_value(true)
+                .conflicts_with_all(&["alpn", "no-content-length", "request-line"])
+        )
+        .arg(
+            Arg::with_name("client-cert")
+                .long("client-cert")
+                .help("PEM file with the client certificate for mutual TLS. Requires --client-key")
+                .value_name("file")
+                .takes_value(true)
+                .conflicts_with_all(&["alpn", "no-content-length", "request-line"])
+        )
+        .arg(
+            Arg::with_name("client-key")
+                .long("client-key")
+                .help("PEM file with the private key of the client certificate (PKCS#8 or RSA)")
+                .value_name("file")
+                .takes_value(true)
+                .conflicts_with_all(&["alpn", "no-content-length", "request-line"])
+        )
+        .arg(
+            Arg::with_name("beautify")
+                .long("beautify")
+                .help("Add new lines to json and html bodies of saved responses. Doesn't affect diffing")
+        )
+        .arg(
+            Arg::with_name("acknowledged")
+                .long("acknowledged")
+                .help("Report parameters whose names (not values) appear in the response, like {\"unknown_field\":\"<name>\"}")
+        )
         .arg(
             Arg::with_name("one-worker-per-host")
                 .long("one-worker-per-host")
@@ -345,6 +702,18 @@ Conflicts with --verify for now.")
             Arg::with_name("check-binary")
                 .long("check-binary")
                 .help("Check the body of responses with binary content types")
+        ).arg(
+            Arg::with_name("binary-mimes")
+                .long("binary-mimes")
+                .help("Additional parts of content types to treat as binary (the bodies aren't compared).\nExample: --binary-mimes wasm,protobuf")
+                .value_name("mimes")
+                .takes_value(true)
+                .conflicts_with("check-binary")
+        ).arg(
+            Arg::with_name("binary-application")
+                .long("binary-application")
+                .help("Treat all application/* content types as binary except the textual ones (json, xml, javascript, ..)")
+                .conflicts_with("check-binary")
         ).arg(
             Arg::with_name("cookies")
                 .long("cookies")
@@ -353,8 +722,149 @@ Conflicts with --verify for now.")
             Arg::with_name("remove-banner")
                 .long("remove-banner")
                 .help("Do not print initial banner")
+        ).arg(
+            Arg::with_name("fragment")
+                .long("fragment")
+                .help("Inject parameters into the url fragment: https://example.com/#%s\nThe fragment isn't sent to the server, so the option works only with --dry-run")
+                .conflicts_with("headers-discovery")
+                .conflicts_with("invert")
+                .requires("dry-run")
+        ).arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Print urls (or requests for non-url injection points) with parameters from the wordlist instead of sending them")
+        ).arg(
+            Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .help("Save the scan progress to the file after every checked chunk of parameters")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .help("Skip parameters that were already checked according to the --checkpoint file")
+                .requires("checkpoint")
+        ).arg(
+            Arg::with_name("gzip-body")
+                .long("gzip-body")
+                .help("Compress the request body with gzip and set the Content-Encoding: gzip header")
+        ).arg(
+            Arg::with_name("connection-close")
+                .long("connection-close")
+                .help("Send Connection: close with every request and open a new connection for every request")
+        ).arg(
+            Arg::with_name("digest-auth")
+                .long("digest-auth")
+                .help("Credentials for HTTP Digest authentication. Example: --digest-auth user:password")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("netrc")
+                .long("netrc")
+                .help("Send Basic authentication credentials of the target host from the .netrc file ($HOME/.netrc by default)")
+                .value_name("file")
+                .min_values(0)
+                .max_values(1)
+                .conflicts_with("digest-auth")
+        ).arg(
+            Arg::with_name("token-refresh-command")
+                .long("token-refresh-command")
+                .help("The command that prints a new bearer token. It runs once the server starts responding with 401")
+                .takes_value(true)
+                .conflicts_with("digest-auth")
+        ).arg(
+            Arg::with_name("aws-sigv4")
+                .long("aws-sigv4")
+                .help("Sign every request with AWS Signature Version 4.\nThe format is access_key:secret_key:region:service[:session_token]")
+                .takes_value(true)
+                .conflicts_with_all(&["digest-auth", "token-refresh-command"])
+        ).arg(
+            Arg::with_name("seq-start")
+                .long("seq-start")
+                .help("The first value of the {{seq}} variable that increases with every request")
+                .default_value("0")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("warc")
+                .long("warc")
+                .help("Write every request and response to the file in WARC 1.1 format")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("har")
+                .long("har")
+                .help("Write every request and response to the file in HAR 1.2 format after the scan.\nCan be imported into Burp, browser devtools and mitmproxy")
+                .value_name("file")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("abort-on-block")
+                .long("abort-on-block")
+                .help("Stop the scan after n similar responses with --block-codes in a row (usually a WAF block page)")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("block-codes")
+                .long("block-codes")
+                .help("Status codes of block pages for --abort-on-block")
+                .default_value("403,429")
+                .use_delimiter(true)
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("block-similarity")
+                .long("block-similarity")
+                .help("Min similarity (0.0-1.0) of the bodies to treat them as the same block page")
+                .default_value("0.9")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("http-log")
+                .long("http-log")
+                .help("Append every request and response to the file with timestamps and status codes")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("http-log-max-size")
+                .long("http-log-max-size")
+                .help("The size of the http log in megabytes after which it's rotated to <filename>.1")
+                .default_value("100")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("exact-requests")
+                .long("exact-requests")
+                .help("Save and log requests byte-for-byte as they were sent (actual http version, header order, added headers)")
+        ).arg(
+            Arg::with_name("detectors")
+                .long("detectors")
+                .help("Additional checks for every response: reflection, code[:<codes>], length:<bytes>, time:<ms>, regex:<pattern>.\nExample: --detectors length:100 'regex:sql syntax' code:500,404->200")
+                .takes_value(true)
+                .min_values(1)
+        ).arg(
+            Arg::with_name("interesting")
+                .long("interesting")
+                .help("Report parameters that make the response match the regex (a target specific marker that the parameter was accepted).\nExample: --interesting '\"debug\":\\{'")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("match-regex")
+                .long("match-regex")
+                .help("Report parameters that make the response body match the regex or stop matching it.\nFor apis that don't reflect values but return specific errors. Example: --match-regex 'missing required field'")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("confirm")
+                .long("confirm")
+                .help("Resend every found parameter n times and report it only in case the finding reproduces")
+                .default_value("0")
+                .takes_value(true)
+        ).arg(
+            Arg::with_name("confirm-threshold")
+                .long("confirm-threshold")
+                .help("The part of the confirmation requests that need to reproduce the finding (0.0-1.0)")
+                .default_value("0.5")
+                .takes_value(true)
         );
 
+    #[cfg(feature = "raw-probe")]
+    let app = app.arg(
+        Arg::with_name("raw-probe")
+            .long("raw-probe")
+            .help("Send the file's content to every url's host as is (over TLS for https urls) and print the raw response's summary")
+            .conflicts_with_all(&["proxy", "http-proxy", "https-proxy", "env-proxy"])
+            .takes_value(true),
+    );
+
     let args = app.clone().get_matches();
 
     if args.value_of("url").is_none() && args.value_of("request").is_none() {
@@ -363,14 +873,50 @@ Conflicts with --verify for now.")
 
     // parse numbers
     let delay = Duration::from_millis(args.value_of("delay").unwrap().parse()?);
+    let delay_jitter = Duration::from_millis(args.value_of("delay-jitter").unwrap().parse()?);
 
     let learn_requests_count = args.value_of("learn-requests-count").unwrap().parse()?;
+    let calibrate = args.value_of("calibrate").unwrap().parse()?;
+    let timing_report = args.value_of("timing-report").unwrap().parse()?;
+    let warm_up = args.value_of("warm-up").unwrap().parse()?;
+    let baselines = args.value_of("baselines").unwrap().parse()?;
+    let baseline_similarity: f64 = args.value_of("baseline-similarity").unwrap().parse()?;
+    if !(0.0..=1.0).contains(&baseline_similarity) {
+        Err("--baseline-similarity should be within 0.0-1.0")?;
+    }
     let concurrency = args.value_of("concurrency").unwrap().parse()?;
     let workers = args.value_of("workers").unwrap().parse()?;
     let verbose = args.value_of("verbose").unwrap().parse()?;
     let timeout = args.value_of("timeout").unwrap().parse()?;
+    let probe_timeout = args.value_of("probe-timeout").unwrap().parse()?;
+    let retry_on_code = match args.values_of("retry-on-code") {
+        Some(vals) => vals
+            .map(|val| {
+                val.trim()
+                    .parse::<u16>()
+                    .map_err(|_| format!("Incorrect --retry-on-code value: {}", val))
+            })
+            .collect::<Result<Vec<u16>, String>>()?,
+        None => Vec::new(),
+    };
+
+    let mut retry_on = Vec::new();
+    for val in args.values_of("retry-on").unwrap() {
+        match ErrorClass::from_name(val) {
+            Some(class) => retry_on.push(class),
+            None if val == "none" => (),
+            None => Err(format!("Incorrect --retry-on value: {}", val))?,
+        }
+    }
     let recursion_depth = args.value_of("recursion-depth").unwrap_or("0").parse()?;
     let progress_bar_len = args.value_of("progress-bar-len").unwrap().parse()?;
+    let seq_start = args.value_of("seq-start").unwrap().parse()?;
+    let confirm = args.value_of("confirm").unwrap().parse()?;
+    let confirm_threshold: f64 = args.value_of("confirm-threshold").unwrap().parse()?;
+
+    if !(0.0..=1.0).contains(&confirm_threshold) {
+        Err("--confirm-threshold should be within 0.0-1.0")?;
+    }
 
     let max: Option<usize> = if args.is_present("max") {
         Some(args.value_of("max").unwrap().parse()?)
@@ -388,7 +934,7 @@ Increase the amount of workers to remove the error or use --force.",
 
     // try to read request file
     let request = match args.value_of("request") {
-        Some(val) => fs::read_to_string(val)?,
+        Some(val) => expand_env_variables(&fs::read_to_string(val)?)?,
         None => String::new(),
     };
 
@@ -398,6 +944,14 @@ Increase the amount of workers to remove the error or use --force.",
                 Some(DataType::Json)
             } else if val == "urlencoded" {
                 Some(DataType::Urlencoded)
+            } else if val == "delimited" {
+                Some(DataType::Delimited)
+            } else if val == "raw" {
+                Some(DataType::Raw)
+            } else if val == "xml" {
+                Some(DataType::Xml)
+            } else if val == "multipart" {
+                Some(DataType::Multipart)
             } else {
                 Err("Incorrect --data-type specified")?
             }
@@ -455,7 +1009,7 @@ Increase the amount of workers to remove the error or use --force.",
                 ]
                 .concat();
 
-                headers.insert(key, value);
+                headers.insert(key, expand_env_variables(&value)?);
             }
         };
 
@@ -497,6 +1051,15 @@ Increase the amount of workers to remove the error or use --force.",
             None
         };
 
+        let body = match args.value_of("body-file") {
+            Some(val) => match fs::read(val) {
+                Ok(body) => String::from_utf8(body)
+                    .map_err(|_| format!("The body file {} isn't valid UTF-8", val))?,
+                Err(err) => Err(format!("Unable to read the body file {}: {}", val, err))?,
+            },
+            None => args.value_of("body").unwrap_or("").to_string(),
+        };
+
         let urls = args.values_of("url").unwrap();
 
         let urls = if urls.len() == 1 && !urls.clone().any(|x| x.contains("://")) {
@@ -509,6 +1072,12 @@ Increase the amount of workers to remove the error or use --force.",
             urls.map(|x| x.to_string()).collect()
         };
 
+        // variables need to be expanded before parsing, otherwise they will be percent-encoded
+        let urls = urls
+            .iter()
+            .map(|x| expand_env_variables(x))
+            .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+
         let urls = urls
             .iter()
             .map(|x| Url::parse(x))
@@ -527,7 +1096,7 @@ Increase the amount of workers to remove the error or use --force.",
                     .map(|x| x.as_ref().unwrap().to_string())
                     .collect::<Vec<String>>(),
                 headers,
-                args.value_of("body").unwrap_or("").to_string(),
+                expand_env_variables(&body)?,
                 data_type,
                 http_version,
             )
@@ -573,6 +1142,25 @@ Increase the amount of workers to remove the error or use --force.",
         custom_parameters.insert(key.to_string(), values);
     }
 
+    // per parameter values replace the custom values for these parameters
+    if let Some(val) = args.value_of("param-values") {
+        let mut param_values: HashMap<String, Vec<String>> = HashMap::new();
+        for line in fs::read_to_string(val)?.lines() {
+            if let Some((key, value)) = line.trim_end_matches('\r').split_once('=') {
+                param_values
+                    .entry(key.to_string())
+                    .or_default()
+                    .push(value.to_string());
+            }
+        }
+
+        // values are popped from the end, so the file order is kept this way
+        for (key, mut values) in param_values {
+            values.reverse();
+            custom_parameters.insert(key, values);
+        }
+    }
+
     // disable colors
     if args.is_present("disable-colors") {
         colored::control::set_override(false);
@@ -605,53 +1193,350 @@ Increase the amount of workers to remove the error or use --force.",
         }
     }
 
+    let checkpoint = match args.value_of("checkpoint") {
+        Some(val) => Some(Arc::new(Mutex::new(Checkpoint::new(
+            val,
+            args.is_present("resume"),
+        )?))),
+        None => None,
+    };
+
+    let digest_auth = match args.value_of("digest-auth") {
+        Some(val) => Some(Arc::new(DigestAuth::new(val)?)),
+        None => None,
+    };
+
+    let encode = if args.is_present("encode") {
+        match args.value_of("encode") {
+            None | Some("single") => EncodeMode::Single,
+            Some("double") => EncodeMode::Double,
+            _ => Err("Incorrect --encode mode specified")?,
+        }
+    } else {
+        EncodeMode::None
+    };
+
+    let encode_set = match args.value_of("encode-chars") {
+        Some(val) => match ascii_set(val) {
+            Some(set) => Some(Arc::new(set)),
+            None => Err("--encode-chars accepts only ascii characters")?,
+        },
+        None => None,
+    };
+
+    let binary_mimes = BinaryMimes::new(
+        &args
+            .value_of("binary-mimes")
+            .map(|x| {
+                x.split(',')
+                    .map(|x| x.trim().to_lowercase())
+                    .filter(|x| !x.is_empty())
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default(),
+        args.is_present("binary-application"),
+    )?;
+
+    let netrc = if args.is_present("netrc") {
+        Some(Netrc::from_file(
+            args.value_of("netrc").unwrap_or_default(),
+        )?)
+    } else {
+        None
+    };
+
+    let token_refresher = args
+        .value_of("token-refresh-command")
+        .map(|val| Arc::new(TokenRefresher::new(val)));
+
+    let signer: Option<Arc<dyn RequestSigner>> = match args.value_of("aws-sigv4") {
+        Some(val) => Some(Arc::new(AwsSigV4::new(val)?)),
+        None => None,
+    };
+
+    let mut detectors = match args.values_of("detectors") {
+        Some(val) => val.map(parse_detector).collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+
+    let match_regex = match args.value_of("match-regex") {
+        Some(val) => Some(Regex::new(val)?),
+        None => None,
+    };
+
+    if match_regex.is_some() {
+        detectors.push(Arc::new(MatchDetector));
+    }
+
+    let interesting: Option<Arc<dyn Detector>> = match args.value_of("interesting") {
+        // a target specific marker of accepted parameters
+        Some(val) => Some(Arc::new(RegexDetector {
+            regex: Regex::new(val)?,
+            reason_kind: ReasonKind::Interesting,
+        })),
+        None => None,
+    };
+
+    let har = match args.value_of("har") {
+        Some(val) => Some(Arc::new(HarWriter::new(val)?)),
+        None => None,
+    };
+
+    let warc = match args.value_of("warc") {
+        Some(val) => Some(Arc::new(WarcWriter::new(val)?)),
+        None => None,
+    };
+
+    // ipv6 addresses should be within brackets: [::1]:8443
+    let connect_to = match args.value_of("connect-to") {
+        Some(val) => match val.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && !val.ends_with(']') => {
+                Some((host.to_string(), Some(port.parse()?)))
+            }
+            _ => Some((val.to_string(), None)),
+        },
+        None => None,
+    };
+
+    // with the request file the port is already a part of the url
+    let port = match args.value_of("port").filter(|_| request.is_empty()) {
+        Some(val) => match val.parse::<u16>() {
+            Ok(port) if port != 0 => Some(port),
+            _ => Err("--port should be within 1-65535")?,
+        },
+        None => None,
+    };
+
+    let block_detector = match args.value_of("abort-on-block") {
+        Some(val) => {
+            let block_similarity: f64 = args.value_of("block-similarity").unwrap().parse()?;
+            if !(0.0..=1.0).contains(&block_similarity) {
+                Err("--block-similarity should be within 0.0-1.0")?;
+            }
+
+            Some(Arc::new(BlockDetector::new(
+                args.values_of("block-codes")
+                    .unwrap()
+                    .map(|x| x.parse())
+                    .collect::<Result<Vec<u16>, _>>()?,
+                block_similarity,
+                val.parse()?,
+            )))
+        }
+        None => None,
+    };
+
+    let host_limiter = match args.value_of("max-host-connections").unwrap().parse()? {
+        0 => None,
+        val => Some(Arc::new(HostLimiter::new(val))),
+    };
+
+    let rate_limiter = match args.value_of("rate-limit") {
+        Some(val) => match val.parse::<u32>()? {
+            0 => Err("--rate-limit should be greater than 0")?,
+            val => Some(Arc::new(RateLimiter::new(val))),
+        },
+        None => None,
+    };
+
+    let http_log_max_size: u64 = args.value_of("http-log-max-size").unwrap().parse()?;
+    let http_log = match args.value_of("http-log") {
+        Some(val) => Some(Arc::new(HttpLogger::new(
+            val,
+            http_log_max_size * 1024 * 1024,
+        )?)),
+        None => None,
+    };
+
+    // check that the files from {{file:path}} templates are readable to show the error as early as possible
+    for value in headers
+        .iter()
+        .map(|(_, v)| v)
+        .chain(urls.iter())
+        .chain([&body])
+    {
+        replace_file_templates(value)?;
+    }
+
+    let client_cert_path = convert_to_string_if_some(args.value_of("client-cert"));
+    let client_key_path = convert_to_string_if_some(args.value_of("client-key"));
+
+    // fail before the scan starts instead of on every client creation
+    client_identity(client_cert_path.as_deref(), client_key_path.as_deref())?;
+
+    let ca_bundle = convert_to_string_if_some(args.value_of("ca-bundle"));
+    if let Some(ca_bundle) = &ca_bundle {
+        ca_certificates(ca_bundle)?;
+    }
+
+    let mut host_headers: HashMap<String, Vec<(String, String)>> = HashMap::new();
+
+    if let Some(val) = args.values_of("host-headers") {
+        for host_header in val {
+            let mut host_k_v = host_header.splitn(2, '=');
+            let host = host_k_v.next().unwrap().trim();
+            let mut k_v = host_k_v
+                .next()
+                .ok_or("Unable to parse host headers")?
+                .splitn(2, ':');
+            let key = k_v.next().unwrap().trim();
+            let value =
+                expand_env_variables(k_v.next().ok_or("Unable to parse host headers")?.trim())?;
+
+            host_headers
+                .entry(host.to_string())
+                .or_default()
+                .push((key.to_string(), value));
+        }
+    }
+
     // TODO maybe replace empty with None
     Ok(Config {
         urls,
         methods,
-        wordlist: args.value_of("wordlist").unwrap_or("").to_string(),
+        param_regex: args.value_of("param-regex").map(Regex::new).transpose()?,
+        wordlists: args
+            .values_of("wordlist")
+            .map(|x| x.map(|x| x.to_string()).collect())
+            .unwrap_or_default(),
         custom_parameters,
         proxy,
+        http_proxy: args.value_of("http-proxy").unwrap_or("").to_string(),
+        https_proxy: args.value_of("https-proxy").unwrap_or("").to_string(),
+        env_proxy: args.is_present("env-proxy"),
+        connect_to,
+        port,
         replay_proxy: args.value_of("replay-proxy").unwrap_or("").to_string(),
         replay_once: args.is_present("replay-once"),
         output_file: args.value_of("output").unwrap_or("").to_string(),
+        manifest_file: args.value_of("manifest").unwrap_or("").to_string(),
         save_responses: args.value_of("save-responses").unwrap_or("").to_string(),
+        save_diffs: args.is_present("save-diffs"),
         output_format: args.value_of("output-format").unwrap_or("").to_string(),
         append: args.is_present("append"),
         remove_empty: args.is_present("remove-empty"),
+        merge_findings: args.is_present("merge-findings") || args.is_present("query-and-body"),
         force: args.is_present("force"),
         strict: args.is_present("strict"),
         disable_progress_bar: args.is_present("disable-progress-bar"),
         progress_bar_len,
         follow_redirects: args.is_present("follow-redirects"),
+        follow_client_redirects: args.is_present("follow-client-redirects"),
         test: args.is_present("test"),
         verbose,
         learn_requests_count,
+        calibrate,
+        timing_report,
+        warm_up,
+        warm_up_confirm: args.is_present("warm-up-confirm"),
+        baselines,
+        baseline_similarity,
         concurrency,
         workers,
         timeout,
+        probe_timeout,
+        retry_on,
+        retry_on_code,
+        cache_buster: args.value_of("cache-buster").unwrap().to_string(),
+        retry_on_match: match args.value_of("retry-on-match") {
+            Some(val) => Some(Regex::new(val)?),
+            None => None,
+        },
+        max_response_headers: args.value_of("max-response-headers").unwrap().parse()?,
+        max_response_headers_size: args
+            .value_of("max-response-headers-size")
+            .unwrap()
+            .parse()?,
         recursion_depth,
         verify: args.is_present("verify"),
         reflected_only: args.is_present("reflected-only"),
+        acknowledged: args.is_present("acknowledged"),
+        beautify: args.is_present("beautify"),
+        no_content_length: args.is_present("no-content-length"),
+        request_line: convert_to_string_if_some(args.value_of("request-line")),
+        tls_info: args.is_present("tls-info"),
+        #[cfg(feature = "raw-probe")]
+        raw_probe: match args.value_of("raw-probe") {
+            Some(val) => Some(
+                fs::read(val)
+                    .map_err(|err| format!("Unable to read --raw-probe {}: {}", val, err))?,
+            ),
+            None => None,
+        },
+        ca_bundle,
+        client_cert_path,
+        client_key_path,
+        alpn: args.values_of("alpn").map(|val| {
+            val.filter(|x| *x != "none")
+                .map(|x| x.to_string())
+                .collect()
+        }),
+        protocol_diff: args.is_present("protocol-diff"),
+        auth_diff: args.is_present("auth-diff"),
+        content_type: convert_to_string_if_some(args.value_of("content-type")),
+        disable_auto_content_type: args.is_present("disable-auto-content-type"),
         http_version,
         template: convert_to_string_if_some(args.value_of("parameter-template")),
         joiner: convert_to_string_if_some(args.value_of("joiner")),
-        encode: args.is_present("encode"),
+        encode,
+        encode_set,
         disable_custom_parameters: args.is_present("disable-custom-parameters"),
         one_worker_per_host: args.is_present("one-worker-per-host"),
         invert: args.is_present("invert"),
+        query_and_body: args.is_present("query-and-body"),
         headers_discovery: args.is_present("headers-discovery") || args.is_present("cookies"),
+        cookie_discovery: args.is_present("cookie-discovery"),
+        include_headers: args
+            .values_of("include-headers")
+            .map(|x| x.map(pattern_to_regex).collect())
+            .transpose()?
+            .unwrap_or_default(),
+        exclude_headers: args
+            .values_of("exclude-headers")
+            .map(|x| x.map(pattern_to_regex).collect())
+            .transpose()?
+            .unwrap_or_default(),
+        fragment: args.is_present("fragment"),
+        literal_body: args.is_present("literal-body"),
+        json_array_entries: args.is_present("json-array-entries"),
+        json_path: convert_to_string_if_some(args.value_of("json-path")),
+        query_candidates: args.is_present("query-candidates"),
+        dry_run: args.is_present("dry-run"),
         body,
         delay,
+        delay_jitter,
         custom_headers: headers
             .iter()
             .map(|(k, v)| (k.to_string(), v.to_string()))
             .collect(),
+        host_headers,
         data_type,
         max,
         disable_colors: args.is_present("disable-colors"),
         remove_banner: args.is_present("remove-banner"),
         disable_trustdns: args.is_present("disable-trustdns"),
+        http09_responses: !args.is_present("disable-http09"),
         check_binary: args.is_present("check-binary"),
+        binary_mimes,
+        gzip_body: args.is_present("gzip-body"),
+        connection_close: args.is_present("connection-close"),
+        seq_start,
+        digest_auth,
+        netrc,
+        token_refresher,
+        signer,
+        checkpoint,
+        warc,
+        har,
+        http_log,
+        exact_requests: args.is_present("exact-requests"),
+        block_detector,
+        host_limiter,
+        rate_limiter,
+        detectors,
+        match_regex,
+        interesting,
+        confirm,
+        confirm_threshold,
     })
 }
@@ -0,0 +1,95 @@
+use std::{error::Error, fmt};
+
+/// errors of building request defaults from the url and the request parameters
+#[derive(Debug)]
+pub enum ConfigError {
+    MissingHost,
+
+    /// the default port isn't known for the scheme
+    UnsupportedScheme(String),
+
+    UrlParse(url::ParseError),
+
+    /// the data type can't be used with the injection place
+    UnsupportedDataType,
+
+    /// the raw data type requires %s within the url or the body
+    MissingInjectionPoint,
+
+    /// the json body can't be parsed or the value on the path isn't an object
+    InvalidJsonPath(String),
+
+    Query(QueryError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::MissingHost => write!(f, "Host missing"),
+            ConfigError::UnsupportedScheme(scheme) => write!(f, "Wrong scheme: {}", scheme),
+            ConfigError::UrlParse(err) => write!(f, "Unable to parse the url: {}", err),
+            ConfigError::UnsupportedDataType => {
+                write!(f, "The data type isn't supported for the injection place")
+            }
+            ConfigError::MissingInjectionPoint => {
+                write!(
+                    f,
+                    "The raw data type requires %s within the url or the body"
+                )
+            }
+            ConfigError::InvalidJsonPath(reason) => {
+                write!(f, "Unable to inject into --json-path: {}", reason)
+            }
+            ConfigError::Query(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::UrlParse(err) => Some(err),
+            ConfigError::Query(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<url::ParseError> for ConfigError {
+    fn from(err: url::ParseError) -> Self {
+        ConfigError::UrlParse(err)
+    }
+}
+
+impl From<QueryError> for ConfigError {
+    fn from(err: QueryError) -> Self {
+        ConfigError::Query(err)
+    }
+}
+
+/// errors of building the query from the parameters
+#[derive(Debug)]
+pub enum QueryError {
+    /// the template doesn't contain %k or %v
+    MissingPlaceholder {
+        template: String,
+        placeholder: &'static str,
+    },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::MissingPlaceholder {
+                template,
+                placeholder,
+            } => write!(
+                f,
+                "The parameter template {:?} doesn't contain {}",
+                template, placeholder
+            ),
+        }
+    }
+}
+
+impl Error for QueryError {}
@@ -1,3 +1,4 @@
 pub mod args;
+pub mod error;
 pub mod structs;
 pub mod utils;
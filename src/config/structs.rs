@@ -1,6 +1,22 @@
-use std::{collections::HashMap, time::Duration};
-
-use crate::network::utils::DataType;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+use percent_encoding::AsciiSet;
+use regex::Regex;
+
+use crate::{
+    network::{
+        auth::{DigestAuth, Netrc, TokenRefresher},
+        block::BlockDetector,
+        har::HarWriter,
+        http_log::HttpLogger,
+        limiter::{HostLimiter, RateLimiter},
+        signer::RequestSigner,
+        utils::{BinaryMimes, DataType, EncodeMode, ErrorClass},
+        warc::WarcWriter,
+    },
+    runner::{checkpoint::Checkpoint, detector::Detector},
+};
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -13,11 +29,21 @@ pub struct Config {
     /// custom user supplied headers or default ones
     pub custom_headers: Vec<(String, String)>,
 
+    /// <host, headers> that are added only to requests to the specific host.
+    /// overwrite custom_headers with the same names
+    pub host_headers: HashMap<String, Vec<(String, String)>>,
+
     /// how much to sleep between requests in millisecs
     pub delay: Duration,
 
-    /// user supplied wordlist file
-    pub wordlist: String,
+    /// the delay is randomly changed by up to ± this value for every request
+    pub delay_jitter: Duration,
+
+    /// user supplied wordlist files or urls
+    pub wordlists: Vec<String>,
+
+    /// parameters with names that don't match the regex are skipped
+    pub param_regex: Option<Regex>,
 
     /// max amount of parameters to send per request.
     /// Can be specified by user otherwise detects automatically based on the request method
@@ -30,7 +56,10 @@ pub struct Config {
     pub joiner: Option<String>,
 
     /// whether to encode the query like param1=value1&param2=value2 -> param1%3dvalue1%26param2%3dvalue2
-    pub encode: bool,
+    pub encode: EncodeMode,
+
+    /// characters to encode instead of the default ones (--encode-chars)
+    pub encode_set: Option<Arc<AsciiSet>>,
 
     /// default body
     pub body: String,
@@ -45,21 +74,46 @@ pub struct Config {
     /// proxy server with schema or http:// by default.
     pub proxy: String,
 
+    /// proxy only for http:// urls. Takes precedence over proxy
+    pub http_proxy: String,
+
+    /// proxy only for https:// urls. Takes precedence over proxy
+    pub https_proxy: String,
+
+    /// use HTTP_PROXY, HTTPS_PROXY, ALL_PROXY and NO_PROXY environment variables in case proxy is empty
+    pub env_proxy: bool,
+
+    /// host and optional port to connect to instead of the url's ones
+    pub connect_to: Option<(String, Option<u16>)>,
+
+    /// port to send requests to instead of the url's one
+    pub port: Option<u16>,
+
     /// file to output
     pub output_file: String,
 
+    /// file to write the effective configuration of every run to (--manifest)
+    pub manifest_file: String,
+
     /// whether to append to the output file instead of overwriting
     pub append: bool,
 
     /// do not print outputs of pairs url:method without found parameters
     pub remove_empty: bool,
 
+    /// merge parameters found within the same url via different injection places.
+    /// The output is printed at the end of the scan
+    pub merge_findings: bool,
+
     /// output format for file & stdout outputs
     pub output_format: String,
 
     /// a directory for saving request & responses with found parameters
     pub save_responses: String,
 
+    /// append the diff with the initial response to the saved responses
+    pub save_diffs: bool,
+
     /// ignore some custom errors like when page's size > MAX_PAGE_SIZE
     pub force: bool,
 
@@ -94,6 +148,25 @@ pub struct Config {
     /// doesn't include first two requests made for cookies and initial response
     pub learn_requests_count: usize,
 
+    /// the amount of requests with random parameters to build the profile of nonexistent parameters.
+    /// Findings matching the profile are suppressed. 0 - disabled
+    pub calibrate: usize,
+
+    /// the amount of requests the same as the initial one to check whether the baseline is stable. 0 - disabled
+    pub warm_up: usize,
+
+    /// increase the amount of confirmation requests in case the baseline is unstable
+    pub warm_up_confirm: bool,
+
+    /// the amount of requests the same as the initial one to collect the page's variants from. 1 - only the initial response
+    pub baselines: usize,
+
+    /// the min similarity of the bodies to treat a response as one of the page's variants
+    pub baseline_similarity: f64,
+
+    /// the amount of parameters with the highest median response time to print after the scan. 0 - disabled
+    pub timing_report: usize,
+
     /// checks the same list of parameters with the found parameters until there are no new parameters to be found.
     /// conflicts with --verify for now. Will be updated in the future.
     pub recursion_depth: usize,
@@ -107,6 +180,28 @@ pub struct Config {
     /// http request timeout in seconds
     pub timeout: usize,
 
+    /// timeout in seconds for the discovery requests. 0 - the timeout above is used
+    pub probe_timeout: usize,
+
+    /// transport errors that are retried once
+    pub retry_on: Vec<ErrorClass>,
+
+    /// auto - add a random query parameter to every request in case the response has the Vary header or is cached,
+    /// always, never
+    pub cache_buster: String,
+
+    /// responses with these status codes are retried the same way as transport errors
+    pub retry_on_code: Vec<u16>,
+
+    /// responses that match the regex are retried the same way as transport errors
+    pub retry_on_match: Option<Regex>,
+
+    /// the max amount of response headers to process. 0 - unlimited
+    pub max_response_headers: usize,
+
+    /// the max total size of response headers (names + values) in bytes. 0 - unlimited
+    pub max_response_headers_size: usize,
+
     /// whether the verify found parameters one time more.
     /// in future wil check for _false_potives like when every parameter that starts with _ is found
     pub verify: bool,
@@ -116,6 +211,49 @@ pub struct Config {
     /// but in rare cases its number may be higher
     pub reflected_only: bool,
 
+    /// search for parameters whose names appear in the response
+    pub acknowledged: bool,
+
+    /// beautify json and html bodies of saved responses
+    pub beautify: bool,
+
+    /// send requests without Content-Length over the raw connection
+    pub no_content_length: bool,
+
+    /// the raw request line, {{path}} is replaced with the path with parameters
+    pub request_line: Option<String>,
+
+    /// collect the negotiated TLS version, cipher and ALPN protocol for https urls
+    pub tls_info: bool,
+
+    /// the bytes to send to every url's host as they are (--raw-probe)
+    #[cfg(feature = "raw-probe")]
+    pub raw_probe: Option<Vec<u8>>,
+
+    /// PEM file with CA certificates to verify the server certificates against.
+    /// None - invalid certificates are accepted
+    pub ca_bundle: Option<String>,
+
+    /// PEM files with the client certificate and its private key for mutual TLS
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+
+    /// ALPN protocols to offer in the TLS handshake instead of the ones derived from the http version.
+    /// Empty to not send the ALPN extension at all
+    pub alpn: Option<Vec<String>>,
+
+    /// send parameters over both HTTP/1.1 and HTTP/2 and report the ones that behave differently
+    pub protocol_diff: bool,
+
+    /// resend found parameters without Cookie and Authorization headers and report the ones that work only with them
+    pub auth_diff: bool,
+
+    /// Content-Type that overrides both the detected and the user supplied one
+    pub content_type: Option<String>,
+
+    /// don't add Content-Type automatically
+    pub disable_auto_content_type: bool,
+
     pub one_worker_per_host: bool,
 
     pub http_version: Option<http::Version>,
@@ -124,17 +262,125 @@ pub struct Config {
     /// it's possible to overwrite this behavior by specifying this option
     pub invert: bool,
 
+    /// check the opposite injection place (query <-> body) as well
+    pub query_and_body: bool,
+
     /// true in case the injection points is within the header or the headers are injection point itself
     pub headers_discovery: bool,
 
+    /// parameters are sent within the Cookie header
+    pub cookie_discovery: bool,
+
+    /// only the headers matching one of the patterns are checked in header discovery mode. Empty - every header
+    pub include_headers: Vec<Regex>,
+
+    /// the headers matching one of the patterns are skipped in header discovery mode
+    pub exclude_headers: Vec<Regex>,
+
+    /// inject parameters into the url fragment (after #)
+    pub fragment: bool,
+
+    /// send the body as is, parameters are injected elsewhere
+    pub literal_body: bool,
+
+    /// add parameters to the top-level json array as separate entries instead of adding them to the first object
+    pub json_array_entries: bool,
+
+    /// the dot separated path to the nested object within the json body to inject into (user.profile)
+    pub json_path: Option<String>,
+
+    /// check parameters from the url's query within the body as well
+    pub query_candidates: bool,
+
+    /// print requests with parameters instead of sending them
+    pub dry_run: bool,
+
     pub follow_redirects: bool,
 
+    /// follow meta refresh and javascript redirects on 200 pages
+    pub follow_client_redirects: bool,
+
     pub disable_colors: bool,
 
     pub remove_banner: bool,
 
     pub disable_trustdns: bool,
 
+    /// accept HTTP/0.9 responses (without the status line and headers)
+    pub http09_responses: bool,
+
     /// check body of responses with binary content type
     pub check_binary: bool,
+
+    /// content types with the bodies that aren't checked (--binary-mimes, --binary-application)
+    pub binary_mimes: BinaryMimes,
+
+    /// compress the request body with gzip
+    pub gzip_body: bool,
+
+    /// send Connection: close and don't reuse connections
+    pub connection_close: bool,
+
+    /// the first value of {{seq}}
+    pub seq_start: usize,
+
+    /// credentials for HTTP Digest authentication.
+    /// shared between all the requests in order to reuse the server's nonce
+    pub digest_auth: Option<Arc<DigestAuth>>,
+
+    /// credentials from the .netrc file. The entry is chosen by the target host
+    pub netrc: Option<Netrc>,
+
+    /// runs the command to get a new bearer token once the server responds with 401.
+    /// shared between all the requests in order to refresh the token only once
+    pub token_refresher: Option<Arc<TokenRefresher>>,
+
+    /// computes headers (signatures) for every prepared request
+    pub signer: Option<Arc<dyn RequestSigner>>,
+
+    /// progress of the scan shared between all the runners.
+    /// None in case --checkpoint isn't provided
+    pub checkpoint: Option<Arc<Mutex<Checkpoint>>>,
+
+    /// writes all the traffic to the file in WARC format.
+    /// None in case --warc isn't provided
+    pub warc: Option<Arc<WarcWriter>>,
+
+    /// collects all the traffic and writes it to the file in HAR format after the scan.
+    /// None in case --har isn't provided
+    pub har: Option<Arc<HarWriter>>,
+
+    /// stops the scan after a run of similar block pages.
+    /// None in case --abort-on-block isn't provided
+    pub block_detector: Option<Arc<BlockDetector>>,
+
+    /// limits the amount of simultaneous connections per host.
+    /// None in case --max-host-connections isn't provided
+    pub host_limiter: Option<Arc<HostLimiter>>,
+
+    /// limits the amount of requests per second across the whole scan.
+    /// None in case --rate-limit isn't provided
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// appends every request & response to the file with timestamps and status codes.
+    /// None in case --http-log isn't provided
+    pub http_log: Option<Arc<HttpLogger>>,
+
+    /// save and log the requests as they were sent on the wire instead of the normalized ones
+    pub exact_requests: bool,
+
+    /// additional checks for every response. Empty by default (only the built-in checks are used)
+    pub detectors: Vec<Arc<dyn Detector>>,
+
+    /// marks parameters as interesting when the response matches the regex (--interesting)
+    pub interesting: Option<Arc<dyn Detector>>,
+
+    /// parameters that flip whether the response body matches the regex are reported (--match-regex)
+    pub match_regex: Option<Regex>,
+
+    /// how many times to resend a found parameter to confirm it. 0 disables the confirmation
+    pub confirm: usize,
+
+    /// the part of the confirmation requests (0.0-1.0) that need to reproduce the finding
+    pub confirm_threshold: f64,
 }
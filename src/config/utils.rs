@@ -1,11 +1,14 @@
 use std::{
     collections::HashMap,
+    env,
     error::Error,
     fs::{File, OpenOptions},
     io::{self, BufRead, Write},
 };
 
 use colored::Colorize;
+use lazy_static::lazy_static;
+use regex::Regex;
 
 use crate::network::utils::DataType;
 
@@ -20,6 +23,51 @@ pub(super) fn convert_to_string_if_some(el: Option<&str>) -> Option<String> {
     }
 }
 
+/// X-* -> (?i)^X-.*$. Patterns within slashes are treated as regexes: /^x-(forwarded|real)-/
+pub(super) fn pattern_to_regex(pattern: &str) -> Result<Regex, Box<dyn Error>> {
+    if pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/') {
+        return Ok(Regex::new(&format!(
+            "(?i){}",
+            &pattern[1..pattern.len() - 1]
+        ))?);
+    }
+
+    let pattern = regex::escape(pattern)
+        .replace("\\*", ".*")
+        .replace("\\?", ".");
+
+    Ok(Regex::new(&format!("(?i)^{}$", pattern))?)
+}
+
+/// replaces ${VARIABLE} with the value of the environment variable.
+/// Unset variables cause an error instead of being silently replaced with an empty string
+pub(super) fn expand_env_variables(value: &str) -> Result<String, Box<dyn Error>> {
+    lazy_static! {
+        static ref RE_ENV_VARIABLE: Regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    }
+
+    let mut expanded = String::with_capacity(value.len());
+    let mut last_end = 0;
+
+    for cap in RE_ENV_VARIABLE.captures_iter(value) {
+        let whole = cap.get(0).unwrap();
+        let name = &cap[1];
+
+        let env_value = match env::var(name) {
+            Ok(val) => val,
+            Err(_) => Err(format!("The environment variable {} is not set", name))?,
+        };
+
+        expanded.push_str(&value[last_end..whole.start()]);
+        expanded.push_str(&env_value);
+        last_end = whole.end();
+    }
+
+    expanded.push_str(&value[last_end..]);
+
+    Ok(expanded)
+}
+
 /// parse request from the request file
 pub(super) fn parse_request<'a>(
     request: &'a str,
@@ -142,7 +190,7 @@ pub(super) fn parse_request<'a>(
     ))
 }
 
-pub fn write_banner_config(config: &Config, params: &Vec<String>) {
+pub fn write_banner_config(config: &Config, params: &Vec<String>, skipped_params: usize) {
     let mut output = format!(
         "{}:         {}\n{}:      {}\n{}: {}",
         "urls".green(),
@@ -153,6 +201,14 @@ pub fn write_banner_config(config: &Config, params: &Vec<String>) {
         params.len(),
     );
 
+    if skipped_params != 0 {
+        output += &format!(
+            "\n{}:      {} (--param-regex)",
+            "skipped".yellow(),
+            skipped_params
+        )
+    }
+
     if !config.proxy.is_empty() {
         output += &format!("\n{}:        {}", "proxy".green(), &config.proxy)
     }
@@ -1,6 +1,6 @@
 //! Mostly taken from https://github.com/changeutils/diff-rs/blob/master/src/lib.rs
 
-use std::{collections::VecDeque, io};
+use std::{cmp, collections::VecDeque, io};
 
 pub fn diff(text1: &str, text2: &str) -> io::Result<Vec<String>> {
     let mut processor = Processor::new();
@@ -177,3 +177,109 @@ impl diffs::Diff for Processor {
         Ok(())
     }
 }
+
+/// the amount of unchanged lines around the changes within unified diffs
+const UNIFIED_CONTEXT: usize = 3;
+
+/// returns the diff in the unified format (like `diff -u` without the file names)
+pub fn unified_diff(text1: &str, text2: &str) -> io::Result<String> {
+    let old = text1.lines().collect::<Vec<&str>>();
+    let new = text2.lines().collect::<Vec<&str>>();
+
+    let mut collector = LineCollector {
+        old: &old,
+        new: &new,
+        lines: Vec::new(),
+    };
+    diffs::myers::diff(&mut collector, &old, &new)?;
+    let mut lines = collector.lines;
+
+    // deletions go before insertions within every change, like in diff -u
+    let mut i = 0;
+    while i < lines.len() {
+        let end = (i..lines.len())
+            .find(|x| lines[*x].0 == ' ')
+            .unwrap_or(lines.len());
+        lines[i..end].sort_by_key(|x| x.0 != '-');
+        i = end + 1;
+    }
+
+    let is_changed = |x: &usize| lines[*x].0 != ' ';
+    let mut output = String::new();
+    let mut i = 0;
+
+    while let Some(first) = (i..lines.len()).find(is_changed) {
+        // changes that are close to each other are joined into a single hunk
+        let mut last = first;
+        while let Some(next) = (last + 1..lines.len()).find(is_changed) {
+            if next - last > UNIFIED_CONTEXT * 2 + 1 {
+                break;
+            }
+            last = next;
+        }
+
+        let start = cmp::max(first.saturating_sub(UNIFIED_CONTEXT), i);
+        let end = cmp::min(last + UNIFIED_CONTEXT + 1, lines.len());
+        let hunk = &lines[start..end];
+
+        // the first line of the hunk within each text. In case there are no lines -- the line before the hunk
+        let old_lines = hunk.iter().filter(|x| x.0 != '+').collect::<Vec<_>>();
+        let new_lines = hunk.iter().filter(|x| x.0 != '-').collect::<Vec<_>>();
+        output += &format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_lines.first().map(|x| x.1 + 1).unwrap_or(hunk[0].1),
+            old_lines.len(),
+            new_lines.first().map(|x| x.2 + 1).unwrap_or(hunk[0].2),
+            new_lines.len()
+        );
+
+        for (tag, _, _, line) in hunk {
+            output += &format!("{}{}\n", tag, line);
+        }
+
+        i = end;
+    }
+
+    Ok(output)
+}
+
+/// collects lines as (' ' | '-' | '+', line index within text1, line index within text2, line)
+struct LineCollector<'a> {
+    old: &'a [&'a str],
+    new: &'a [&'a str],
+    lines: Vec<(char, usize, usize, &'a str)>,
+}
+
+impl diffs::Diff for LineCollector<'_> {
+    type Error = io::Error;
+
+    fn equal(&mut self, old: usize, new: usize, len: usize) -> Result<(), Self::Error> {
+        for i in 0..len {
+            self.lines.push((' ', old + i, new + i, self.old[old + i]));
+        }
+
+        Ok(())
+    }
+
+    fn delete(&mut self, old: usize, len: usize) -> Result<(), Self::Error> {
+        // the line index within text2 is the one of the next unchanged line
+        let new = self
+            .lines
+            .last()
+            .map(|x| x.2 + usize::from(x.0 != '-'))
+            .unwrap_or(0);
+        for i in 0..len {
+            self.lines.push(('-', old + i, new, self.old[old + i]));
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, old: usize, new: usize, new_len: usize) -> Result<(), Self::Error> {
+        for i in 0..new_len {
+            self.lines.push(('+', old, new + i, self.new[new + i]));
+        }
+
+        Ok(())
+    }
+}
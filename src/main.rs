@@ -1,5 +1,6 @@
 extern crate x8;
 use std::{
+    collections::HashSet,
     error::Error,
     io::{self, Write},
     iter::FromIterator,
@@ -23,15 +24,19 @@ use x8::{
         utils::{file_writer, write_banner_config},
     },
     network::{
+        raw,
         request::{Request, RequestDefaults},
-        utils::Headers,
+        utils::{create_client, Headers, InjectionPlace},
     },
     runner::{
-        output::{ParseOutputs, RunnerOutput},
+        manifest::RunManifest,
+        output::{merge_findings, ParseOutputs, RunnerOutput},
         runner::Runner,
         utils::{Parameters, ReasonKind},
     },
-    utils::{self, init_progress, read_lines, read_stdin_lines},
+    utils::{
+        self, init_progress, order_by_weight, read_lines, read_remote_lines, read_stdin_lines,
+    },
 };
 
 #[cfg(windows)]
@@ -100,10 +105,17 @@ async fn init() -> Result<(), Box<dyn Error>> {
 
     let mut params: Vec<String> = Vec::new();
 
-    if !config.wordlist.is_empty() {
-        // read parameters from a file
-        for line in read_lines(&config.wordlist)?.flatten() {
-            params.push(line);
+    if !config.wordlists.is_empty() {
+        for wordlist in config.wordlists.iter() {
+            if wordlist.starts_with("http://") || wordlist.starts_with("https://") {
+                // fetch parameters from a remote server
+                params.append(&mut read_remote_lines(&config, wordlist).await?);
+            } else {
+                // read parameters from a file
+                for line in read_lines(wordlist)?.flatten() {
+                    params.push(line);
+                }
+            }
         }
     // just accept piped stdin
     } else if !atty::is(Stream::Stdin) {
@@ -111,6 +123,54 @@ async fn init() -> Result<(), Box<dyn Error>> {
         params = read_stdin_lines();
     }
 
+    let mut params = order_by_weight(params);
+
+    // the tool works properly only with unique parameters
+    // so we are removing duplicates while preserving the first-seen order
+    // (the one with the highest weight in case of weighted wordlists)
+    let mut seen_params = HashSet::new();
+    params.retain(|x| seen_params.insert(x.to_owned()));
+
+    // garbage lines from scraped wordlists produce malformed requests.
+    // Only the names are checked, values (name=value) can contain anything
+    let params_len = params.len();
+    if let Some(param_regex) = &config.param_regex {
+        params.retain(|x| {
+            let is_valid = param_regex.is_match(x.split('=').next().unwrap());
+            if !is_valid {
+                log::warn!("Skipping the parameter with invalid characters: {:?}", x);
+            }
+            is_valid
+        });
+    }
+    let skipped_params = params_len - params.len();
+
+    // print requests with parameters instead of sending them
+    if config.dry_run {
+        for url in config.urls.iter() {
+            for method in config.methods.iter() {
+                let request_defaults =
+                    RequestDefaults::from_config(&config, method.as_str(), url.as_str())?;
+
+                for chunk in params.chunks(config.max.unwrap_or(128)) {
+                    let mut request = Request::new(&request_defaults, chunk.to_vec());
+
+                    let output = match request_defaults.injection_place {
+                        InjectionPlace::Path | InjectionPlace::Fragment => {
+                            request.prepare()?;
+                            request.url()
+                        }
+                        _ => request.print()? + "\n",
+                    };
+
+                    writeln!(io::stdout(), "{}", output).ok();
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
     // open output file
     let mut output_file = if !config.output_file.is_empty() {
         let mut file = OpenOptions::new();
@@ -133,15 +193,37 @@ async fn init() -> Result<(), Box<dyn Error>> {
 
     let shared_output_file = Arc::new(Mutex::new(&mut output_file));
 
+    let manifest = if !config.manifest_file.is_empty() {
+        Some(Arc::new(Mutex::new(RunManifest::new(
+            &config.manifest_file,
+            &config,
+        ))))
+    } else {
+        None
+    };
+
     if !config.remove_banner {
-        write_banner_config(&config, &params);
+        write_banner_config(&config, &params, skipped_params);
     }
 
     // such headers usually cause server to timeout
     // especially when http/2 is used
     // probably better to add a flag for keeping such parameters?
-    if config.headers_discovery {
+    if config.headers_discovery
+        || config
+            .methods
+            .iter()
+            .any(|x| x.eq_ignore_ascii_case("CONNECT"))
+    {
         params.retain(|x| "content-length" != x.to_lowercase() && "host" != x.to_lowercase());
+
+        // --include-headers & --exclude-headers
+        params.retain(|x| {
+            let name = x.split('=').next().unwrap();
+            (config.include_headers.is_empty()
+                || config.include_headers.iter().any(|re| re.is_match(name)))
+                && !config.exclude_headers.iter().any(|re| re.is_match(name))
+        });
     }
 
     // -W 0 is a special option to run everything in parallel
@@ -155,6 +237,7 @@ async fn init() -> Result<(), Box<dyn Error>> {
         futures::stream::iter(init_progress(&config).iter().enumerate().skip(1).map(
             |(id, (progress_bar, url_set))| {
                 let shared_output_file = Arc::clone(&shared_output_file);
+                let manifest = manifest.clone();
 
                 // each url set should have each own list of parameters
                 let params = params.clone();
@@ -170,29 +253,196 @@ async fn init() -> Result<(), Box<dyn Error>> {
                     // for now url set are used only in case --one-worker-per-host option is provided
                     // otherwise it's just url sets of 1 url
                     for url in url_set {
-                        for method in &config.methods.clone() {
+                        // --query-and-body checks every method once more with the opposite injection place
+                        let runs: Vec<(&String, bool)> = config
+                            .methods
+                            .iter()
+                            .flat_map(|method| {
+                                std::iter::once(config.invert)
+                                    .chain(config.query_and_body.then_some(!config.invert))
+                                    .map(move |invert| (method, invert))
+                            })
+                            .collect();
+
+                        // (method, injection place) pairs that were already checked
+                        let mut checked_runs: Vec<(&String, InjectionPlace)> = Vec::new();
+
+                        for (method, invert) in runs {
                             // each method should have each own list of parameters (we're changing this list through the run)
                             let mut params = params.clone();
 
-                            let mut request_defaults = match RequestDefaults::from_config(
-                                config,
-                                method.as_str(),
-                                url.as_str(),
-                            ) {
-                                Ok(val) => val,
-                                Err(err) => {
-                                    utils::error(err, Some(url), Some(progress_bar), Some(config));
-                                    continue;
+                            let mut request_defaults =
+                                match create_client(config, false).and_then(|client| {
+                                    RequestDefaults::from_config_with_client(
+                                        config,
+                                        client,
+                                        method.as_str(),
+                                        url.as_str(),
+                                        invert,
+                                    )
+                                }) {
+                                    Ok(val) => val,
+                                    Err(err) => {
+                                        utils::error(
+                                            err,
+                                            Some(url),
+                                            Some(progress_bar),
+                                            Some(config),
+                                        );
+                                        continue;
+                                    }
+                                };
+
+                            // the opposite run makes sense only when it moves the parameters between the query and the body
+                            if invert != config.invert
+                                && (!matches!(
+                                    request_defaults.injection_place,
+                                    InjectionPlace::Path | InjectionPlace::Body
+                                ) || checked_runs
+                                    .contains(&(method, request_defaults.injection_place)))
+                            {
+                                continue;
+                            }
+                            checked_runs.push((method, request_defaults.injection_place));
+
+                            if let Some(manifest) = &manifest {
+                                if let Err(err) = manifest.lock().add_run(&request_defaults) {
+                                    utils::info(
+                                        config,
+                                        id,
+                                        progress_bar,
+                                        "~",
+                                        format!("Unable to write the manifest: {}", err),
+                                    );
                                 }
-                            };
+                            }
+
+                            // skip parameters that were checked before the scan was interrupted
+                            if let Some(checkpoint) = &config.checkpoint {
+                                if let Some(entry) = checkpoint.lock().get(&request_defaults) {
+                                    let tested: HashSet<&String> = entry.tested.iter().collect();
+                                    params.retain(|x| !tested.contains(x));
+                                }
+                            }
 
                             // get cookies
-                            if let Err(err) =
-                                Request::new(&request_defaults, Vec::new()).send().await
+                            let (vary, cache_hit) =
+                                match Request::new(&request_defaults, Vec::new()).send().await {
+                                    Ok(val) => (
+                                        val.headers.get_value_case_insensitive("vary"),
+                                        val.cache_hit(),
+                                    ),
+                                    Err(err) => {
+                                        utils::error(
+                                            err,
+                                            Some(url),
+                                            Some(progress_bar),
+                                            Some(config),
+                                        );
+                                        continue;
+                                    }
+                                };
+
+                            if let Some(cache_hit) = &cache_hit {
+                                utils::info(
+                                    config,
+                                    id,
+                                    progress_bar,
+                                    "~",
+                                    format!("The response seems to be cached ({})", cache_hit),
+                                );
+                            }
+
+                            // caches that key on the Vary headers may return the same response regardless of parameters
+                            if config.cache_buster == "auto"
+                                && (vary.is_some() || cache_hit.is_some())
                             {
-                                utils::error(err, Some(url), Some(progress_bar), Some(config));
-                                continue;
-                            };
+                                request_defaults.cache_buster = true;
+                                utils::info(
+                                    config,
+                                    id,
+                                    progress_bar,
+                                    "cache",
+                                    format!(
+                                        "adding a random parameter to every request (Vary: {})",
+                                        vary.as_deref().unwrap_or("-")
+                                    ),
+                                );
+                            }
+
+                            if config.tls_info && request_defaults.scheme == "https" {
+                                let (host, port) = request_defaults.connection_target();
+                                match raw::tls_info(
+                                    host,
+                                    port,
+                                    config.http_version,
+                                    config.alpn.as_ref(),
+                                    request_defaults.timeout,
+                                )
+                                .await
+                                {
+                                    Ok(val) => {
+                                        utils::info(config, id, progress_bar, "tls", &val);
+                                        request_defaults.tls_info = Some(Arc::new(val));
+                                    }
+                                    Err(err) => utils::info(
+                                        config,
+                                        id,
+                                        progress_bar,
+                                        "~",
+                                        format!("Unable to collect TLS info: {}", err),
+                                    ),
+                                }
+                            }
+
+                            #[cfg(feature = "raw-probe")]
+                            if let Some(bytes) = &config.raw_probe {
+                                let (host, port) = request_defaults.connection_target();
+                                match raw::probe(
+                                    host,
+                                    port,
+                                    request_defaults.scheme == "https",
+                                    config.alpn.as_ref(),
+                                    bytes,
+                                    request_defaults.timeout,
+                                )
+                                .await
+                                {
+                                    Ok(val) => {
+                                        // compare with the usual response to notice desyncs
+                                        let usual = Request::new(&request_defaults, Vec::new())
+                                            .send()
+                                            .await
+                                            .map(|x| x.code.to_string())
+                                            .unwrap_or_else(|err| err.to_string());
+
+                                        utils::info(
+                                            config,
+                                            id,
+                                            progress_bar,
+                                            "probe",
+                                            format!("{} (usual code: {})", val, usual),
+                                        );
+
+                                        if config.verbose > 1 {
+                                            utils::info(
+                                                config,
+                                                id,
+                                                progress_bar,
+                                                "probe",
+                                                String::from_utf8_lossy(&val.raw),
+                                            );
+                                        }
+                                    }
+                                    Err(err) => utils::info(
+                                        config,
+                                        id,
+                                        progress_bar,
+                                        "~",
+                                        format!("Unable to send the raw probe: {}", err),
+                                    ),
+                                }
+                            }
 
                             match run(
                                 config,
@@ -205,7 +455,11 @@ async fn init() -> Result<(), Box<dyn Error>> {
                             {
                                 Ok(val) => {
                                     // if output format is not json we can print output and write to file in real time
-                                    if config.output_format != "json"  {
+                                    if config.output_format != "json"
+                                        && config.output_format != "openapi"
+                                        && config.output_format != "markdown"
+                                        && !config.merge_findings
+                                    {
                                         let mut output_file = shared_output_file.lock();
                                         let output = val.parse(config);
 
@@ -273,13 +527,18 @@ async fn init() -> Result<(), Box<dyn Error>> {
         .collect::<Vec<Vec<RunnerOutput>>>()
         .await;
 
-    // works only in case json output is used.
+    // works only in case json output is used or findings are merged.
     // otherwise runner_outputs is an empty vector
     // and all the printing work is done within the futures above
-    if !runner_outputs.is_empty() && config.output_format != "standart" {
+    if !runner_outputs.is_empty() && (config.output_format != "standart" || config.merge_findings) {
+        let mut runner_outputs: Vec<RunnerOutput> = runner_outputs.into_iter().flatten().collect();
+
+        if config.merge_findings {
+            runner_outputs = merge_findings(runner_outputs);
+        }
+
         let output = runner_outputs
             .into_iter()
-            .flatten()
             .filter(|x| !(config.remove_empty && x.found_params.is_empty()))
             .collect::<Vec<RunnerOutput>>()
             .parse_output(&config);
@@ -288,7 +547,7 @@ async fn init() -> Result<(), Box<dyn Error>> {
             output_file
                 .as_mut()
                 .unwrap()
-                .write_all(output.as_bytes())
+                .write_all(&strip_ansi_escapes::strip(output.as_bytes()).unwrap())
                 .await?;
             output_file.as_mut().unwrap().flush().await?;
         }
@@ -296,6 +555,10 @@ async fn init() -> Result<(), Box<dyn Error>> {
         write!(io::stdout(), "\n{}", output).ok();
     }
 
+    if let Some(har) = &config.har {
+        har.write()?;
+    }
+
     Ok(())
 }
 
@@ -369,6 +632,17 @@ async fn run(
         }
     }
 
+    // add parameters that were found before the scan was interrupted
+    if let Some(checkpoint) = &config.checkpoint {
+        if let Some(entry) = checkpoint.lock().get(request_defaults) {
+            for param in entry.found_params.iter() {
+                if !runner_output.found_params.contains_element(param) {
+                    runner_output.found_params.push(param.clone());
+                }
+            }
+        }
+    }
+
     // we probably changed request_defaults.parameters within the loop above
     // so we are removing all of the added parameters in there
     // leaving only user-supplied ones
@@ -380,7 +654,7 @@ async fn run(
         .map(|x| x.to_owned())
         .collect();
 
-    runner_output.prepare(config, request_defaults);
+    runner_output.prepare(config, request_defaults)?;
 
     Ok(runner_output)
 }
@@ -0,0 +1,347 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+
+use crate::utils::random_line;
+
+/// HTTP Digest authentication (RFC 7616, MD5 and MD5-sess only)
+#[derive(Debug, Default)]
+pub struct DigestAuth {
+    username: String,
+    password: String,
+
+    /// the last challenge from the server. Reused for every request until the server sends a new one
+    challenge: Mutex<Option<DigestChallenge>>,
+
+    /// nonce count. Needs to be increased with every request that uses the same nonce
+    nc: AtomicUsize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    qop: Option<String>,
+    algorithm: String,
+}
+
+impl DigestAuth {
+    /// parses user:password
+    pub fn new(credentials: &str) -> Result<Self, Box<dyn Error>> {
+        let mut credentials = credentials.splitn(2, ':');
+
+        Ok(Self {
+            username: credentials.next().unwrap().to_string(),
+            password: credentials
+                .next()
+                .ok_or("Unable to parse digest credentials. The format is user:password")?
+                .to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// returns the Authorization header value in case the challenge was received before
+    pub fn authorization(&self, method: &str, uri: &str) -> Option<String> {
+        let challenge = self.challenge.lock().clone()?;
+
+        let nc = format!("{:08x}", self.nc.fetch_add(1, Ordering::SeqCst) + 1);
+        let cnonce = random_line(16);
+
+        let mut ha1 = md5_hex(&format!(
+            "{}:{}:{}",
+            self.username, challenge.realm, self.password
+        ));
+        if challenge.algorithm.eq_ignore_ascii_case("MD5-sess") {
+            ha1 = md5_hex(&format!("{}:{}:{}", ha1, challenge.nonce, cnonce));
+        }
+
+        let ha2 = md5_hex(&format!("{}:{}", method, uri));
+
+        let mut authorization = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}",
+            self.username, challenge.realm, challenge.nonce, uri, challenge.algorithm
+        );
+
+        // only qop=auth is supported. auth-int would require hashing the body
+        if challenge.qop.is_some() {
+            let response = md5_hex(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, challenge.nonce, nc, cnonce, ha2
+            ));
+            authorization += &format!(
+                ", response=\"{}\", qop=auth, nc={}, cnonce=\"{}\"",
+                response, nc, cnonce
+            );
+        } else {
+            let response = md5_hex(&format!("{}:{}:{}", ha1, challenge.nonce, ha2));
+            authorization += &format!(", response=\"{}\"", response);
+        }
+
+        if let Some(opaque) = challenge.opaque {
+            authorization += &format!(", opaque=\"{}\"", opaque);
+        }
+
+        Some(authorization)
+    }
+
+    /// saves the challenge from WWW-Authenticate header of 401 response
+    /// and returns the Authorization header value to repeat the request with
+    pub fn answer_challenge(
+        &self,
+        headers: &HeaderMap,
+        method: &str,
+        uri: &str,
+    ) -> Option<HeaderValue> {
+        lazy_static! {
+            static ref RE_DIGEST_PARAMS: Regex =
+                Regex::new(r#"(\w+)=(?:"([^"]*)"|([^,\s]*))"#).unwrap();
+        }
+
+        let header = headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|x| x.to_str().ok())
+            .find(|x| {
+                x.get(..7)
+                    .is_some_and(|x| x.eq_ignore_ascii_case("digest "))
+            })?;
+
+        let params: HashMap<String, String> = RE_DIGEST_PARAMS
+            .captures_iter(&header[7..])
+            .map(|cap| {
+                (
+                    cap[1].to_lowercase(),
+                    cap.get(2)
+                        .or_else(|| cap.get(3))
+                        .map(|x| x.as_str().to_string())
+                        .unwrap_or_default(),
+                )
+            })
+            .collect();
+
+        let challenge = DigestChallenge {
+            realm: params.get("realm").cloned().unwrap_or_default(),
+            nonce: params.get("nonce")?.to_owned(),
+            opaque: params.get("opaque").cloned(),
+            qop: params
+                .get("qop")
+                .filter(|x| x.split(',').any(|x| x.trim() == "auth"))
+                .cloned(),
+            algorithm: params
+                .get("algorithm")
+                .cloned()
+                .unwrap_or_else(|| "MD5".to_string()),
+        };
+
+        if !challenge.algorithm.eq_ignore_ascii_case("MD5")
+            && !challenge.algorithm.eq_ignore_ascii_case("MD5-sess")
+        {
+            log::debug!("Unsupported digest algorithm: {}", challenge.algorithm);
+            return None;
+        }
+
+        *self.challenge.lock() = Some(challenge);
+        self.nc.store(0, Ordering::SeqCst);
+
+        HeaderValue::from_str(&self.authorization(method, uri)?).ok()
+    }
+}
+
+fn md5_hex(value: &str) -> String {
+    format!("{:x}", md5::compute(value))
+}
+
+/// refreshes the bearer token with the user supplied command once the server starts responding with 401
+#[derive(Debug, Default)]
+pub struct TokenRefresher {
+    /// shell command that prints the new token to stdout
+    command: String,
+
+    /// the last received token. None until the first refresh (the user supplied Authorization header is used)
+    token: Mutex<Option<String>>,
+
+    /// increases with every refresh.
+    /// Requests remember it before sending, so in case a lot of concurrent requests got 401 the token is refreshed only once
+    generation: AtomicUsize,
+
+    /// only one refresh at a time
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+impl TokenRefresher {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// returns the current token and its generation
+    pub fn token(&self) -> (Option<String>, usize) {
+        (
+            self.token.lock().clone(),
+            self.generation.load(Ordering::SeqCst),
+        )
+    }
+
+    /// runs the command in case the token wasn't refreshed since the request with the expired token was sent
+    /// returns the new token
+    pub async fn refresh(&self, generation: usize) -> Result<String, Box<dyn Error>> {
+        let _lock = self.refresh_lock.lock().await;
+
+        // somebody else has already refreshed the token
+        if self.generation.load(Ordering::SeqCst) != generation {
+            if let Some(token) = self.token.lock().clone() {
+                return Ok(token);
+            }
+        }
+
+        let output = if cfg!(windows) {
+            tokio::process::Command::new("cmd")
+                .args(["/C", &self.command])
+                .output()
+                .await?
+        } else {
+            tokio::process::Command::new("sh")
+                .args(["-c", &self.command])
+                .output()
+                .await?
+        };
+
+        if !output.status.success() {
+            Err(format!(
+                "The token refresh command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))?;
+        }
+
+        let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        if token.is_empty() {
+            Err("The token refresh command returned an empty token")?;
+        }
+
+        *self.token.lock() = Some(token.clone());
+        self.generation.fetch_add(1, Ordering::SeqCst);
+
+        Ok(token)
+    }
+}
+
+/// credentials from a .netrc file (machine, login, password and default tokens)
+#[derive(Debug, Default, Clone)]
+pub struct Netrc {
+    /// (machine, login, password)
+    machines: Vec<(String, String, String)>,
+
+    /// (login, password) of the default entry
+    default: Option<(String, String)>,
+}
+
+impl Netrc {
+    /// reads the file. Uses $HOME/.netrc in case the path is empty
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let path = if path.is_empty() {
+            let home = std::env::var("HOME")
+                .or_else(|_| std::env::var("USERPROFILE"))
+                .map_err(|_| "Unable to find the home directory for the .netrc file")?;
+            std::path::Path::new(&home)
+                .join(".netrc")
+                .to_string_lossy()
+                .to_string()
+        } else {
+            path.to_string()
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Unable to read {}: {}", path, err))?;
+
+        Ok(Self::parse(&content))
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut netrc = Self::default();
+
+        // (machine, login, password). machine is None for the default entry
+        let mut entry: Option<(Option<String>, String, String)> = None;
+
+        let save = |netrc: &mut Self, entry: Option<(Option<String>, String, String)>| {
+            match entry {
+                Some((Some(machine), login, password)) => {
+                    netrc.machines.push((machine, login, password))
+                }
+                // only the first default entry counts
+                Some((None, login, password)) if netrc.default.is_none() => {
+                    netrc.default = Some((login, password))
+                }
+                _ => (),
+            }
+        };
+
+        let mut tokens = content
+            .lines()
+            // only whole lines are comments, so passwords can contain #
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split_whitespace());
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" => {
+                    save(&mut netrc, entry.take());
+                    entry = tokens
+                        .next()
+                        .map(|x| (Some(x.to_lowercase()), String::new(), String::new()));
+                }
+                "default" => {
+                    save(&mut netrc, entry.take());
+                    entry = Some((None, String::new(), String::new()));
+                }
+                "login" | "password" => {
+                    let value = tokens.next().unwrap_or_default().to_string();
+                    if let Some(entry) = entry.as_mut() {
+                        if token == "login" {
+                            entry.1 = value;
+                        } else {
+                            entry.2 = value;
+                        }
+                    }
+                }
+                // account <value>
+                "account" => {
+                    tokens.next();
+                }
+                // macdef <name> and the macro body are ignored
+                _ => (),
+            }
+        }
+        save(&mut netrc, entry);
+
+        netrc
+    }
+
+    /// returns (login, password) for the host. Falls back to the default entry
+    pub fn credentials(&self, host: &str) -> Option<(String, String)> {
+        self.machines
+            .iter()
+            .find(|(machine, _, _)| machine.eq_ignore_ascii_case(host))
+            .map(|(_, login, password)| (login.to_owned(), password.to_owned()))
+            .or_else(|| self.default.clone())
+    }
+
+    /// returns the Authorization header value for the host
+    pub fn basic_auth(&self, host: &str) -> Option<String> {
+        let (login, password) = self.credentials(host)?;
+        Some(format!(
+            "Basic {}",
+            base64::encode(format!("{}:{}", login, password))
+        ))
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+/// watches for a run of similar block-like responses (usually a WAF block page)
+/// and stops the scan once the run is long enough
+#[derive(Debug)]
+pub struct BlockDetector {
+    /// status codes of block pages
+    codes: Vec<u16>,
+
+    /// min similarity (0.0-1.0) of two bodies to treat them as the same block page
+    threshold: f64,
+
+    /// the amount of block-like responses in a row needed to stop the scan
+    limit: usize,
+
+    /// the current run length and the body of the last block-like response
+    state: Mutex<(usize, String)>,
+
+    /// once set, it stays set even if the following responses aren't blocked
+    blocked: AtomicBool,
+
+    /// wakes up the requests waiting in blocked()
+    notify: Notify,
+}
+
+impl BlockDetector {
+    pub fn new(codes: Vec<u16>, threshold: f64, limit: usize) -> Self {
+        Self {
+            codes,
+            threshold,
+            limit,
+            state: Mutex::new((0, String::new())),
+            blocked: AtomicBool::new(false),
+            notify: Notify::new(),
+        }
+    }
+
+    /// remembers the response. Returns true in case the run of block-like responses reached the limit
+    pub fn observe(&self, code: u16, text: &str) -> bool {
+        let mut state = self.state.lock();
+
+        if !self.codes.contains(&code) {
+            state.0 = 0;
+        } else if state.0 > 0 && similarity(&state.1, text) >= self.threshold {
+            state.0 += 1;
+        } else {
+            *state = (1, text.to_string());
+        }
+
+        if state.0 >= self.limit {
+            self.blocked.store(true, Ordering::SeqCst);
+            self.notify.notify_waiters();
+        }
+
+        self.is_blocked()
+    }
+
+    /// whether the scan was stopped
+    pub fn is_blocked(&self) -> bool {
+        self.blocked.load(Ordering::SeqCst)
+    }
+
+    /// resolves once the scan is stopped
+    pub async fn blocked(&self) {
+        loop {
+            // the future is created before the check, so the notification can't be missed in between
+            let notified = self.notify.notified();
+            if self.is_blocked() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// jaccard similarity of the sets of words. 1.0 - the same words, 0.0 - no common words
+pub fn similarity(first: &str, second: &str) -> f64 {
+    let first: HashSet<&str> = first.split_whitespace().collect();
+    let second: HashSet<&str> = second.split_whitespace().collect();
+
+    if first.is_empty() && second.is_empty() {
+        return 1.0;
+    }
+
+    first.intersection(&second).count() as f64 / first.union(&second).count() as f64
+}
@@ -0,0 +1,139 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use url::Url;
+
+use super::{request::Request, utils::version_to_str, warc::format_date};
+
+/// collects every request & response pair and writes them to a HAR 1.2 file after the scan.
+/// The format can be imported into Burp, browser devtools and mitmproxy
+#[derive(Debug)]
+pub struct HarWriter {
+    filename: String,
+    entries: Mutex<Vec<Value>>,
+}
+
+impl HarWriter {
+    /// creates the file right away, so a wrong path is reported before the scan
+    pub fn new(filename: &str) -> Result<Self, Box<dyn Error>> {
+        File::create(filename)?;
+
+        Ok(Self {
+            filename: filename.to_string(),
+            entries: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// the time it took to get the response is reported as the waiting time
+    pub fn add(
+        &self,
+        request: &Request,
+        http_version: http::Version,
+        code: u16,
+        headers: &[(String, String)],
+        body: &[u8],
+        time: Duration,
+    ) {
+        let url = request.url();
+        let response_date = SystemTime::now();
+        let request_date = response_date.checked_sub(time).unwrap_or(response_date);
+        let version = version_to_str(http_version);
+
+        let query_string: Vec<Value> = Url::parse(&url)
+            .map(|x| {
+                x.query_pairs()
+                    .map(|(k, v)| json!({"name": k, "value": v}))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut har_request = json!({
+            "method": request.defaults.method,
+            "url": url,
+            "httpVersion": version,
+            "cookies": [],
+            "headers": har_headers(&request.headers),
+            "queryString": query_string,
+            "headersSize": -1,
+            "bodySize": request.body.len(),
+        });
+
+        if !request.body.is_empty() {
+            har_request["postData"] = json!({
+                "mimeType": header_value(&request.headers, "content-type"),
+                "text": request.body,
+            });
+        }
+
+        let entry = json!({
+            "startedDateTime": format_date(request_date),
+            "time": time.as_millis(),
+            "request": har_request,
+            "response": {
+                "status": code,
+                "statusText": http::StatusCode::from_u16(code)
+                    .ok()
+                    .and_then(|x| x.canonical_reason())
+                    .unwrap_or_default(),
+                "httpVersion": version,
+                "cookies": [],
+                "headers": har_headers(headers),
+                "content": {
+                    "size": body.len(),
+                    "mimeType": header_value(headers, "content-type"),
+                    "text": String::from_utf8_lossy(body),
+                },
+                "redirectURL": header_value(headers, "location"),
+                "headersSize": -1,
+                "bodySize": body.len(),
+            },
+            "cache": {},
+            "timings": {
+                "send": 0,
+                "wait": time.as_millis(),
+                "receive": 0,
+            },
+        });
+
+        self.entries.lock().push(entry);
+    }
+
+    /// writes all the collected entries to the file
+    pub fn write(&self) -> Result<(), Box<dyn Error>> {
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": "x8",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": *self.entries.lock(),
+            }
+        });
+
+        fs::write(&self.filename, serde_json::to_string_pretty(&har)?)?;
+
+        Ok(())
+    }
+}
+
+/// the value of the header or an empty string in case there's no such header
+fn header_value(headers: &[(String, String)], name: &str) -> String {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.to_owned())
+        .unwrap_or_default()
+}
+
+fn har_headers(headers: &[(String, String)]) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(k, v)| json!({"name": k, "value": v}))
+        .collect()
+}
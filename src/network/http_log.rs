@@ -0,0 +1,59 @@
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    time::SystemTime,
+};
+
+use parking_lot::Mutex;
+
+use super::{response::Response, warc::format_date};
+
+/// appends every request & response pair to the file in plain text.
+/// Once the file exceeds max_size bytes it's renamed to <filename>.1 (the previous .1 is replaced) and a new file is started
+#[derive(Debug)]
+pub struct HttpLogger {
+    filename: String,
+    max_size: u64,
+    /// the opened file and its current size
+    file: Mutex<(File, u64)>,
+}
+
+impl HttpLogger {
+    pub fn new(filename: &str, max_size: u64) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(filename)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            filename: filename.to_string(),
+            max_size,
+            file: Mutex::new((file, size)),
+        })
+    }
+
+    pub fn write(&self, response: &Response) -> Result<(), Box<dyn Error>> {
+        let entry = format!(
+            "=== #{} {} {} {}ms ===\n{}\n\n",
+            response.index,
+            format_date(SystemTime::now()),
+            response.code,
+            response.time,
+            response.print_all()
+        );
+
+        let mut file = self.file.lock();
+
+        if file.1 > 0 && file.1 + entry.len() as u64 > self.max_size {
+            fs::rename(&self.filename, format!("{}.1", self.filename))?;
+            *file = (File::create(&self.filename)?, 0);
+        }
+
+        file.0.write_all(entry.as_bytes())?;
+        file.1 += entry.len() as u64;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+/// limits the amount of simultaneous connections to every host.
+/// Shared between all the urls, so it's applied even with several workers checking the same host
+#[derive(Debug)]
+pub struct HostLimiter {
+    /// the max amount of simultaneous connections per host
+    limit: usize,
+
+    /// host:port -> semaphore
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// waits until there's a free connection slot for the host.
+    /// The slot is released once the permit is dropped
+    pub async fn acquire(&self, host: &str, port: u16) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self
+            .semaphores
+            .lock()
+            .entry(format!("{}:{}", host, port))
+            .or_insert_with(|| Arc::new(Semaphore::new(self.limit)))
+            .clone();
+
+        // the semaphore is never closed
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+/// limits the amount of requests per second across the whole scan.
+/// Requests get evenly spaced time slots, so there are no bursts even after a pause
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// the time between two requests
+    interval: Duration,
+
+    /// the earliest time the next request can be sent at
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / requests_per_second,
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// waits for the request's time slot
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+
+        tokio::time::sleep_until(slot).await;
+    }
+}
@@ -1,5 +1,13 @@
+pub mod auth;
+pub mod block;
+pub mod har;
+pub mod http_log;
+pub mod limiter;
+pub mod raw;
 pub mod request;
 pub mod response;
+pub mod signer;
 pub mod utils;
+pub mod warc;
 
 mod tests;
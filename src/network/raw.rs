@@ -0,0 +1,428 @@
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    io::{self, Read},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate, ClientConfig, ServerName,
+    },
+    TlsConnector,
+};
+
+/// negotiated TLS parameters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TlsInfo {
+    /// for example, TLSv1_3
+    pub version: String,
+
+    /// for example, TLS13_AES_256_GCM_SHA384
+    pub cipher: String,
+
+    /// None in case the server didn't select any of the offered protocols
+    pub alpn: Option<String>,
+}
+
+impl fmt::Display for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} (alpn: {})",
+            self.version,
+            self.cipher,
+            self.alpn.as_deref().unwrap_or("none")
+        )
+    }
+}
+
+/// reqwest doesn't expose the negotiated TLS parameters, so they are collected via a separate handshake
+/// with the same ALPN protocols reqwest offers
+pub async fn tls_info(
+    host: &str,
+    port: u16,
+    http_version: Option<http::Version>,
+    alpn: Option<&Vec<String>>,
+    timeout: Duration,
+) -> Result<TlsInfo, Box<dyn Error>> {
+    let alpn = match (alpn, http_version) {
+        (Some(alpn), _) => alpn_protocols(alpn),
+        (None, Some(http::Version::HTTP_11)) => vec![b"http/1.1".to_vec()],
+        (None, Some(http::Version::HTTP_2)) => vec![b"h2".to_vec()],
+        _ => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+    };
+
+    let stream = tokio::time::timeout(timeout, async {
+        let stream = TcpStream::connect((host, port)).await?;
+
+        Ok::<_, Box<dyn Error>>(
+            TlsConnector::from(Arc::new(tls_config(alpn)))
+                .connect(ServerName::try_from(host)?, stream)
+                .await?,
+        )
+    })
+    .await
+    .map_err(|_| "The handshake timed out")??;
+
+    let (_, connection) = stream.get_ref();
+
+    Ok(TlsInfo {
+        version: connection
+            .protocol_version()
+            .map(|x| format!("{:?}", x))
+            .unwrap_or_default(),
+        cipher: connection
+            .negotiated_cipher_suite()
+            .map(|x| format!("{:?}", x.suite()))
+            .unwrap_or_default(),
+        alpn: connection
+            .alpn_protocol()
+            .map(|x| String::from_utf8_lossy(x).to_string()),
+    })
+}
+
+/// http/1.1 -> b"http/1.1"
+pub(super) fn alpn_protocols(alpn: &[String]) -> Vec<Vec<u8>> {
+    alpn.iter().map(|x| x.as_bytes().to_vec()).collect()
+}
+
+/// accepts invalid certificates, the same as danger_accept_invalid_certs for the reqwest client
+pub(super) fn tls_config(alpn: Vec<Vec<u8>>) -> ClientConfig {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(NoCertificateVerification))
+        .with_no_client_auth();
+
+    config.alpn_protocols = alpn;
+
+    config
+}
+
+/// the response received over the raw connection
+#[derive(Debug, Default)]
+pub struct RawResponse {
+    pub code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+
+    /// the exact bytes written to the connection
+    pub request: Vec<u8>,
+}
+
+/// writes the request to the socket directly. Used for requests that reqwest (hyper) can't send:
+/// - without Content-Length and Transfer-Encoding (content_length is false).
+///   The body is delimited by closing the write half of the connection (HTTP/1.0 style)
+/// - with an arbitrary request line (absolute-form uri, bogus version, ..)
+///
+/// The response is read until the server closes the connection.
+/// Only direct HTTP/1.1 connections are supported (no proxies, no HTTP/2)
+pub async fn send(
+    request: &http::Request<Vec<u8>>,
+    request_line: &str,
+    content_length: bool,
+    timeout: Duration,
+) -> Result<RawResponse, Box<dyn Error>> {
+    let uri = request.uri();
+    let host = uri.host().ok_or("The url doesn't contain the host")?;
+    let is_https = uri.scheme_str() == Some("https");
+    let port = uri.port_u16().unwrap_or(if is_https { 443 } else { 80 });
+
+    let mut raw_request = format!("{}\r\n", request_line);
+
+    if !request.headers().contains_key(http::header::HOST) {
+        raw_request += &format!("Host: {}\r\n", uri.authority().unwrap());
+    }
+
+    if content_length
+        && !request.body().is_empty()
+        && !request.headers().contains_key(http::header::CONTENT_LENGTH)
+    {
+        raw_request += &format!("Content-Length: {}\r\n", request.body().len());
+    }
+
+    for (k, v) in request.headers().iter() {
+        raw_request += &format!("{}: {}\r\n", title_case(k.as_str()), v.to_str()?);
+    }
+
+    if !request.headers().contains_key(http::header::CONNECTION) {
+        raw_request += "Connection: close\r\n";
+    }
+
+    let mut raw_request = (raw_request + "\r\n").into_bytes();
+    raw_request.extend(request.body());
+
+    let response = tokio::time::timeout(timeout, async {
+        let stream = TcpStream::connect((host, port)).await?;
+
+        if is_https {
+            let stream = TlsConnector::from(Arc::new(tls_config(Vec::new())))
+                .connect(ServerName::try_from(host)?, stream)
+                .await?;
+
+            exchange(stream, &raw_request).await
+        } else {
+            exchange(stream, &raw_request).await
+        }
+    })
+    .await
+    // io::Error to be classified as a timeout for --retry-on
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "The request timed out"))??;
+
+    let mut response = parse_response(&response)?;
+    response.request = raw_request;
+
+    Ok(response)
+}
+
+/// the response to the raw probe (--raw-probe)
+#[cfg(feature = "raw-probe")]
+#[derive(Debug, Default)]
+pub struct ProbeResponse {
+    /// the bytes received before the connection was closed or the timeout expired
+    pub raw: Vec<u8>,
+
+    /// None in case the received bytes aren't a valid HTTP/1 response
+    pub response: Option<RawResponse>,
+
+    /// time from the sent request to the last received byte
+    pub time: Duration,
+}
+
+#[cfg(feature = "raw-probe")]
+impl fmt::Display for ProbeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.response {
+            Some(response) => write!(
+                f,
+                "code {}, {} bytes ({} received in {}ms)",
+                response.code,
+                response.body.len(),
+                self.raw.len(),
+                self.time.as_millis()
+            ),
+            None => write!(
+                f,
+                "no valid response ({} bytes received in {}ms)",
+                self.raw.len(),
+                self.time.as_millis()
+            ),
+        }
+    }
+}
+
+/// writes the bytes to the connection exactly as they are and reads until the server closes the connection
+/// or the timeout expires. Nothing is added or fixed within the request, so it's suitable for desync and smuggling tests.
+/// The TLS config is the same as the client's one, but only HTTP/1.1 is offered unless --alpn is provided
+#[cfg(feature = "raw-probe")]
+pub async fn probe(
+    host: &str,
+    port: u16,
+    is_https: bool,
+    alpn: Option<&Vec<String>>,
+    bytes: &[u8],
+    timeout: Duration,
+) -> Result<ProbeResponse, Box<dyn Error>> {
+    let alpn = alpn.map_or_else(|| vec![b"http/1.1".to_vec()], |x| alpn_protocols(x));
+
+    let stream = tokio::time::timeout(timeout, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "The connection timed out"))??;
+
+    let start = std::time::Instant::now();
+
+    let raw = if is_https {
+        let stream = tokio::time::timeout(
+            timeout,
+            TlsConnector::from(Arc::new(tls_config(alpn)))
+                .connect(ServerName::try_from(host)?, stream),
+        )
+        .await
+        .map_err(|_| "The handshake timed out")??;
+
+        probe_exchange(stream, bytes, timeout).await?
+    } else {
+        probe_exchange(stream, bytes, timeout).await?
+    };
+
+    Ok(ProbeResponse {
+        response: parse_response(&raw).ok(),
+        raw,
+        time: start.elapsed(),
+    })
+}
+
+/// unlike exchange(), the write half isn't closed because some servers drop such connections,
+/// and the partially received response is kept in case the timeout expires
+#[cfg(feature = "raw-probe")]
+async fn probe_exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    bytes: &[u8],
+    timeout: Duration,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    stream.write_all(bytes).await?;
+
+    let mut raw = Vec::new();
+    let mut buf = [0; 8192];
+
+    let _ = tokio::time::timeout(timeout, async {
+        loop {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => raw.extend_from_slice(&buf[..n]),
+            }
+        }
+    })
+    .await;
+
+    Ok(raw)
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    raw_request: &[u8],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    stream.write_all(raw_request).await?;
+    // the end of the body
+    stream.shutdown().await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    Ok(response)
+}
+
+fn parse_response(response: &[u8]) -> Result<RawResponse, Box<dyn Error>> {
+    let mut response = response;
+
+    loop {
+        let headers_end = response
+            .windows(4)
+            .position(|x| x == b"\r\n\r\n")
+            .ok_or("Unable to parse the response")?;
+
+        let head = String::from_utf8_lossy(&response[..headers_end]).to_string();
+        let body = &response[headers_end + 4..];
+
+        let mut lines = head.split("\r\n");
+
+        let code: u16 = lines
+            .next()
+            .and_then(|x| x.split(' ').nth(1))
+            .ok_or("Unable to parse the status line")?
+            .parse()?;
+
+        // skip 100 Continue and similar informational responses
+        if (100..200).contains(&code) {
+            response = body;
+            continue;
+        }
+
+        let headers: Vec<(String, String)> = lines
+            .filter_map(|x| x.split_once(':'))
+            .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+            .collect();
+
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_lowercase())
+        };
+
+        let mut body = if header("transfer-encoding").is_some_and(|x| x.contains("chunked")) {
+            decode_chunked(body)
+        } else if let Some(content_length) =
+            header("content-length").and_then(|x| x.parse::<usize>().ok())
+        {
+            body[..content_length.min(body.len())].to_vec()
+        } else {
+            body.to_vec()
+        };
+
+        if header("content-encoding").is_some_and(|x| x == "gzip") {
+            let mut decoded = Vec::new();
+            if GzDecoder::new(body.as_slice())
+                .read_to_end(&mut decoded)
+                .is_ok()
+            {
+                body = decoded;
+            }
+        }
+
+        return Ok(RawResponse {
+            code,
+            headers,
+            body,
+            request: Vec::new(),
+        });
+    }
+}
+
+/// decodes as much as possible. Truncated bodies are returned partially
+fn decode_chunked(mut body: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+
+    while let Some(size_end) = body.windows(2).position(|x| x == b"\r\n") {
+        let size = String::from_utf8_lossy(&body[..size_end]);
+        let size = match usize::from_str_radix(size.split(';').next().unwrap().trim(), 16) {
+            Ok(val) => val,
+            Err(_) => break,
+        };
+
+        if size == 0 {
+            break;
+        }
+
+        let chunk = &body[size_end + 2..];
+        decoded.extend(&chunk[..size.min(chunk.len())]);
+
+        body = match chunk.get(size + 2..) {
+            Some(val) => val,
+            None => break,
+        };
+    }
+
+    decoded
+}
+
+/// content-type -> Content-Type, as reqwest does with http1_title_case_headers
+pub(super) fn title_case(header: &str) -> String {
+    header
+        .split('-')
+        .map(|x| {
+            let mut chars = x.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// the same as danger_accept_invalid_certs for the reqwest client
+struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
@@ -1,16 +1,38 @@
-use crate::{config::structs::Config, utils::random_line, RANDOM_LENGTH, VALUE_LENGTH};
+use crate::{
+    config::{
+        error::{ConfigError, QueryError},
+        structs::Config,
+    },
+    utils::random_line,
+    RANDOM_LENGTH, VALUE_LENGTH,
+};
+use flate2::{write::GzEncoder, Compression};
 use itertools::Itertools;
 use lazy_static::lazy_static;
-use percent_encoding::utf8_percent_encode;
+use percent_encoding::{percent_decode_str, AsciiSet};
+use rand::Rng;
 use regex::Regex;
-use reqwest::Client;
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, HeaderValue, ACCEPT, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_LENGTH,
+        HOST, RANGE,
+    },
+    Client,
+};
+use serde_json::{Map, Value};
 use std::{
     collections::HashMap,
     convert::TryFrom,
     error::Error,
+    io::Write,
     iter::FromIterator,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
+use tokio::sync::Semaphore;
 use url::Url;
 
 /// in order to be able to use make_query() for headers as well
@@ -18,11 +40,48 @@ const HEADERS_TEMPLATE: &str = "%k\x00@%=%@\x00%v";
 const HEADERS_MIDDLE: &str = "\x00@%=%@\x00";
 const HEADERS_JOINER: &str = "\x01@%&%@\x01";
 
+pub const BLOCKED_ERROR: &str =
+    "The scan was stopped because of too many block pages in a row (--abort-on-block)";
+
+pub const HTTP09_ERROR: &str =
+    "The server sent an HTTP/0.9 (or non-HTTP) response while they are disabled (--disable-http09)";
+
 use super::{
+    auth::{DigestAuth, TokenRefresher},
+    block::BlockDetector,
+    har::HarWriter,
+    http_log::HttpLogger,
+    limiter::{HostLimiter, RateLimiter},
+    raw::{self, title_case, TlsInfo},
     response::Response,
-    utils::{create_client, is_binary_content, DataType, Headers, InjectionPlace, FRAGMENT},
+    signer::RequestSigner,
+    utils::{
+        client_redirect, create_client, is_binary_content, is_invalid_version,
+        replace_file_templates, unescape, version_to_str, BinaryMimes, DataType, EncodeMode,
+        ErrorClass, Headers, InjectionPlace, FRAGMENT,
+    },
+    warc::WarcWriter,
 };
 
+/// options of RequestDefaults::new that change where and how parameters are injected
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InjectionOptions<'b> {
+    /// parameters are sent within the Cookie header
+    pub cookie_discovery: bool,
+
+    /// parameters are sent after '#'
+    pub fragment: bool,
+
+    /// the body is sent as is, parameters are sent within the query
+    pub literal_body: bool,
+
+    /// every parameter is a separate object of the json array
+    pub json_array_entries: bool,
+
+    /// the dot separated path of the nested json object to inject into
+    pub json_path: Option<&'b str>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct RequestDefaults {
     /// default request data
@@ -38,7 +97,11 @@ pub struct RequestDefaults {
     /// how much to sleep between requests in millisecs
     pub delay: Duration, //MOVE to config
 
-    /// default reqwest client
+    /// the delay is randomly changed by up to ±delay_jitter for every request
+    pub delay_jitter: Duration,
+
+    /// default reqwest client.
+    /// It's reference counted, so the clones share the same connection pool and cookies
     pub client: Client,
 
     /// parameter template, for example %k=%v
@@ -48,10 +111,14 @@ pub struct RequestDefaults {
     pub joiner: String,
 
     /// whether to encode the query like param1=value1&param2=value2 -> param1%3dvalue1%26param2%3dvalue2
-    pub encode: bool,
+    pub encode: EncodeMode,
+
+    /// characters to encode from --encode-chars. FRAGMENT is used in case it's None
+    pub encode_set: Option<Arc<AsciiSet>>,
 
-    /// to replace {"key": "false"} with {"key": false}
-    pub is_json: bool,
+    /// the format parameters are sent in.
+    /// Determines the query building and the Content-Type for the body injection
+    pub data_type: DataType,
 
     /// default body
     pub body: String,
@@ -66,11 +133,131 @@ pub struct RequestDefaults {
     /// where the injection point is
     pub injection_place: InjectionPlace,
 
+    /// the injection point is the value of an existing parameter (id=%s),
+    /// so the words are sent as its value one per request instead of being added as parameters
+    pub value_injection: bool,
+
     /// the default amount of reflection per non existing parameter
     pub amount_of_reflections: usize,
 
     /// check body of responses with binary content type
     pub check_binary: bool,
+
+    /// content types with the bodies that aren't checked
+    pub binary_mimes: BinaryMimes,
+
+    /// the body is sent as is: without injection points and template variables
+    pub literal_body: bool,
+
+    /// limits the amount of in-flight requests across every RequestDefaults sharing the semaphore.
+    /// The permit is acquired after the delay and released once the response body is read,
+    /// so the delay still applies to every request, while the semaphore bounds only concurrency, not the rate.
+    /// None by default (no limits)
+    pub semaphore: Option<Arc<Semaphore>>,
+
+    /// writes every request & response to the WARC file
+    pub warc: Option<Arc<WarcWriter>>,
+
+    /// collects the traffic for the HAR file (--har)
+    pub har: Option<Arc<HarWriter>>,
+
+    /// appends every request & response to the plain text log
+    pub http_log: Option<Arc<HttpLogger>>,
+
+    /// host and port to connect to instead of the url's ones. The url's host is sent within the Host header
+    pub connect_to: Option<(String, u16)>,
+
+    /// port to send requests to instead of the url's one. The url's port is still sent within the Host header
+    pub port_override: Option<u16>,
+
+    /// stops sending requests after a run of block pages
+    pub block_detector: Option<Arc<BlockDetector>>,
+
+    /// limits the amount of simultaneous connections to every host. Unlike the semaphore, it's shared between all the urls
+    pub host_limiter: Option<Arc<HostLimiter>>,
+
+    /// limits the amount of requests per second across all the urls
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// compress the body with gzip before sending (Content-Encoding: gzip)
+    pub gzip_body: bool,
+
+    /// send Connection: close with every request
+    pub connection_close: bool,
+
+    /// follow meta refresh and javascript redirects on 200 pages (once)
+    pub follow_client_redirects: bool,
+
+    /// keep the serialized request within the response (Response.raw_request)
+    pub exact_requests: bool,
+
+    /// the version the client is limited to (--http). None in case it's negotiated
+    pub http_version: Option<http::Version>,
+
+    /// credentials for HTTP Digest authentication
+    pub digest_auth: Option<Arc<DigestAuth>>,
+
+    /// Authorization: Basic header value taken from the .netrc file
+    pub basic_auth: Option<String>,
+
+    /// refreshes the bearer token on 401
+    pub token_refresher: Option<Arc<TokenRefresher>>,
+
+    /// adds headers computed over the prepared request (signatures)
+    pub signer: Option<Arc<dyn RequestSigner>>,
+
+    /// beautify response bodies for displaying
+    pub beautify: bool,
+
+    /// the Content-Type that is always sent regardless of the detected one and custom headers
+    pub content_type_override: Option<String>,
+
+    /// don't set Content-Type automatically, only the user supplied headers are sent
+    pub disable_auto_content_type: bool,
+
+    /// send requests without Content-Length and Transfer-Encoding headers over the raw connection
+    pub no_content_length: bool,
+
+    /// the request line that is sent instead of "<method> <path> HTTP/1.1" over the raw connection.
+    /// {{path}} is replaced with the path with parameters
+    pub request_line: Option<String>,
+
+    /// the timeout for requests over the raw connection (reqwest's one is set within the client)
+    pub timeout: Duration,
+
+    /// overrides the client's timeout for every request. Zero - the client's one is used
+    pub request_timeout: Duration,
+
+    /// transport errors that are retried once
+    pub retry_on: Vec<ErrorClass>,
+
+    /// add a random query parameter to every request to avoid cached responses
+    pub cache_buster: bool,
+
+    /// retry responses with these status codes (gateway errors)
+    pub retry_on_code: Vec<u16>,
+
+    /// retry responses that match the regex (transient errors with 200 codes)
+    pub retry_on_match: Option<Regex>,
+
+    /// the custom found-condition for the response body (--match-regex)
+    pub match_regex: Option<Regex>,
+
+    /// the max amount of response headers to process. 0 - unlimited
+    pub max_response_headers: usize,
+
+    /// the max total size of response headers (names + values). 0 - unlimited
+    pub max_response_headers_size: usize,
+
+    /// negotiated TLS parameters of the host, collected once with --tls-info
+    pub tls_info: Option<Arc<TlsInfo>>,
+
+    /// the amount of responses received for the url. Shared between the clones
+    pub responses_count: Arc<AtomicUsize>,
+
+    /// the value of {{seq}}. Increases with every prepared request.
+    /// shared between clones, so every request within the run gets the next value
+    pub seq: Arc<AtomicUsize>,
 }
 
 #[derive(Debug, Clone)]
@@ -98,6 +285,9 @@ pub struct Request<'a> {
     /// whether the request was prepared
     /// {{random}} things replaced, prepared_parameters filled
     pub prepared: bool,
+
+    /// separates multipart/form-data parts. Empty for other data types
+    pub boundary: String,
 }
 
 impl<'a> Request<'a> {
@@ -111,6 +301,11 @@ impl<'a> Request<'a> {
             prepared_parameters: Vec::new(), //l.parameters.clone(),
             non_random_parameters: Vec::new(),
             prepared: false,
+            boundary: if l.data_type == DataType::Multipart {
+                format!("----x8{}", random_line(16))
+            } else {
+                String::new()
+            },
         }
     }
 
@@ -132,18 +327,42 @@ impl<'a> Request<'a> {
     pub fn url(&self) -> String {
         format!(
             "{}://{}:{}{}",
-            &self.defaults.scheme, &self.defaults.host, &self.defaults.port, &self.path
+            &self.defaults.scheme,
+            &self.defaults.host,
+            self.defaults.target_port(),
+            &self.path
         )
     }
 
-    pub fn make_query(&self) -> String {
+    /// the url the connection is made to. Differs from url() with --connect-to
+    pub fn connection_url(&self) -> String {
+        let (host, port) = self.defaults.connection_target();
+        format!(
+            "{}://{}:{}{}",
+            &self.defaults.scheme, host, port, &self.path
+        )
+    }
+
+    /// errors in case the template misses one of the placeholders
+    pub fn make_query(&self) -> Result<String, QueryError> {
+        // the query without parameters is empty regardless of the template
+        if !self.prepared_parameters.is_empty() || !self.defaults.parameters.is_empty() {
+            RequestDefaults::check_template(
+                &self.defaults.template,
+                self.defaults.injection_place,
+                self.defaults.value_injection,
+            )?;
+        }
+
         lazy_static! {
             static ref RE_JSON_WORDS_WITHOUT_QUOTES: Regex =
                 Regex::new(r#"^([1-9]\d*|null|false|true)$"#).unwrap();
         }
 
-        let query = if self.defaults.is_json {
-            self.prepared_parameters
+        let query = match self.defaults.data_type {
+            // to replace {"key": "false"} with {"key": false}
+            DataType::Json => self
+                .prepared_parameters
                 .iter()
                 .chain(self.defaults.parameters.iter())
                 // not very optimal because we know that there's a lot of random parameters
@@ -159,47 +378,87 @@ impl<'a> Request<'a> {
                     }
                 })
                 .collect::<Vec<String>>()
-                .join(&self.defaults.joiner)
-        } else {
-            self.prepared_parameters
+                .join(&self.defaults.joiner),
+            // every part starts with the boundary, and the closing boundary ends the body
+            DataType::Multipart => {
+                self.prepared_parameters
+                    .iter()
+                    .chain(self.defaults.parameters.iter())
+                    .map(|(k, v)| {
+                        format!(
+                            "--{}\r\n{}\r\n",
+                            self.boundary,
+                            self.defaults.template.replace("%k", k).replace("%v", v)
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join(&self.defaults.joiner)
+                    + &format!("--{}--\r\n", self.boundary)
+            }
+            _ => self
+                .prepared_parameters
                 .iter()
                 .chain(self.defaults.parameters.iter())
                 .map(|(k, v)| self.defaults.template.replace("%k", k).replace("%v", v))
                 .collect::<Vec<String>>()
-                .join(&self.defaults.joiner)
+                .join(&self.defaults.joiner),
         };
 
-        if self.defaults.encode {
-            utf8_percent_encode(&query, &FRAGMENT).to_string()
-        } else {
-            query
+        Ok(self.defaults.encode.encode(
+            query,
+            self.defaults.encode_set.as_deref().unwrap_or(&FRAGMENT),
+        ))
+    }
+
+    /// replaces template variables within the value:
+    /// {{random}} - with a random value
+    /// {{seq}} - with the sequential number of the request
+    /// {{file:path}} - with the trimmed content of the file
+    fn replace_templates(&self, value: &str, seq: usize) -> String {
+        let value = value
+            .replace("{{random}}", &random_line(RANDOM_LENGTH))
+            .replace("{{seq}}", &seq.to_string());
+
+        // the files are checked while parsing the config
+        // so the error here means that the file was changed during the scan
+        match replace_file_templates(&value) {
+            Ok(val) => val,
+            Err(err) => {
+                log::error!("{}", err);
+                value
+            }
         }
     }
 
     /// replace injection points with parameters
-    /// replace templates ({{random}}) with random values
+    /// replace templates ({{random}}, {{seq}}, {{file:path}}) with their values
     /// additional param is for reflection counting TODO REMOVE
     ///
     /// in case self.parameters contains parameter with "="
     /// it gets splitted by =  and the default random value gets replaced with the right part:
     /// admin=true -> (admin, true) vs admin -> (admin, df32w)
-    pub fn prepare(&mut self) {
+    pub fn prepare(&mut self) -> Result<(), QueryError> {
         if self.prepared {
-            return;
+            return Ok(());
         }
         self.prepared = true;
 
+        // imported parameters may be percent-encoded already, so they are decoded
+        // in order to not get encoded twice within make_query
+        let decode = |x: &str| {
+            if self.defaults.encode != EncodeMode::None {
+                percent_decode_str(x).decode_utf8_lossy().to_string()
+            } else {
+                x.to_owned()
+            }
+        };
+
         self.non_random_parameters = Vec::from_iter(
             self.parameters
                 .iter()
                 .filter(|x| x.contains('='))
                 .map(|x| x.split('='))
-                .map(|mut x| {
-                    (
-                        x.next().unwrap().to_owned(),
-                        x.next().unwrap_or("").to_owned(),
-                    )
-                }),
+                .map(|mut x| (decode(x.next().unwrap()), decode(x.next().unwrap_or("")))),
         );
 
         self.prepared_parameters = Vec::from_iter(
@@ -222,65 +481,107 @@ impl<'a> Request<'a> {
                 ),
         );
 
+        // every {{seq}} within the request shares the same value
+        let seq = self.defaults.seq.fetch_add(1, Ordering::SeqCst);
+
         if self.defaults.injection_place != InjectionPlace::HeaderValue {
             for (k, v) in self.defaults.custom_headers.iter() {
-                self.set_header(k, &v.replace("{{random}}", &random_line(RANDOM_LENGTH)));
+                let v = self.replace_templates(v, seq);
+                self.set_header(k, &v);
             }
         }
-        self.path = self.path.replace("{{random}}", &random_line(RANDOM_LENGTH));
-        self.body = self.body.replace("{{random}}", &random_line(RANDOM_LENGTH));
+
+        // the connection goes to another host or port, so the original one is sent within the Host header
+        if (self.defaults.connect_to.is_some() || self.defaults.port_override.is_some())
+            && self
+                .defaults
+                .custom_headers
+                .get_index_case_insensitive("host")
+                .is_none()
+        {
+            let host = self.defaults.authority();
+            self.set_header("Host", &host);
+        }
+
+        // the user supplied Authorization header has priority over the .netrc credentials
+        if let Some(basic_auth) = &self.defaults.basic_auth {
+            if self
+                .defaults
+                .custom_headers
+                .get_index_case_insensitive("authorization")
+                .is_none()
+            {
+                self.set_header("Authorization", basic_auth);
+            }
+        }
+
+        self.path = self.replace_templates(&self.path, seq);
+        if !self.defaults.literal_body {
+            self.body = self.replace_templates(&self.body, seq);
+        }
+
+        // the body itself gets compressed right before sending
+        if self.defaults.gzip_body
+            && self
+                .defaults
+                .custom_headers
+                .get_index_case_insensitive("content-encoding")
+                .is_none()
+        {
+            self.set_header("Content-Encoding", "gzip");
+        }
+
+        if self.defaults.connection_close
+            && self
+                .defaults
+                .custom_headers
+                .get_index_case_insensitive("connection")
+                .is_none()
+        {
+            self.set_header("Connection", "close");
+        }
 
         match self.defaults.injection_place {
-            InjectionPlace::Path => self.path = self.path.replace("%s", &self.make_query()),
-            InjectionPlace::Body => {
-                self.body = self.body.replace("%s", &self.make_query());
+            InjectionPlace::Path | InjectionPlace::Fragment => {
+                self.path = self.path.replace("%s", &self.make_query()?);
 
-                if !self.defaults.custom_headers.contains_key("Content-Type") {
-                    if self.defaults.is_json {
-                        self.set_header("Content-Type", "application/json");
-                    } else {
-                        self.set_header("Content-Type", "application/x-www-form-urlencoded");
+                if self.defaults.literal_body {
+                    self.set_body_content_type();
+                }
+            }
+            InjectionPlace::Body => {
+                self.body = self.body.replace("%s", &self.make_query()?);
+
+                match self.defaults.data_type {
+                    DataType::Json => self.set_auto_content_type("application/json"),
+                    DataType::Delimited => self.set_auto_content_type("text/plain"),
+                    DataType::Xml => self.set_auto_content_type("application/xml"),
+                    DataType::Multipart => {
+                        let content_type =
+                            format!("multipart/form-data; boundary={}", self.boundary);
+                        self.set_auto_content_type(&content_type);
                     }
+                    DataType::Raw => {}
+                    _ => self.set_auto_content_type("application/x-www-form-urlencoded"),
                 }
             }
             InjectionPlace::HeaderValue => {
                 // in case someone searches headers while sending a valid body - it's usually important to set Content-Type header as well.
-                if !self.defaults.custom_headers.contains_key("Content-Type")
-                    && self.defaults.method != "GET"
-                    && self.defaults.method != "HEAD"
-                    && !self.body.is_empty()
-                {
-                    if self.body.starts_with('{') {
-                        self.set_header("Content-Type", "application/json");
-                    } else {
-                        self.set_header("Content-Type", "application/x-www-form-urlencoded");
-                    }
-                }
+                self.set_body_content_type();
 
                 for (k, v) in self.defaults.custom_headers.iter() {
-                    self.set_header(
-                        k,
-                        &v.replace("{{random}}", &random_line(RANDOM_LENGTH))
-                            .replace("%s", &self.make_query()),
-                    );
+                    let v = self
+                        .replace_templates(v, seq)
+                        .replace("%s", &self.make_query()?);
+                    self.set_header(k, &v);
                 }
             }
             InjectionPlace::Headers => {
                 // in case someone searches headers while sending a valid body - it's usually important to set Content-Type header as well.
-                if !self.defaults.custom_headers.contains_key("Content-Type")
-                    && self.defaults.method != "GET"
-                    && self.defaults.method != "HEAD"
-                    && !self.body.is_empty()
-                {
-                    if self.body.starts_with('{') {
-                        self.set_header("Content-Type", "application/json");
-                    } else {
-                        self.set_header("Content-Type", "application/x-www-form-urlencoded");
-                    }
-                }
+                self.set_body_content_type();
 
                 let headers: Vec<(String, String)> = self
-                    .make_query()
+                    .make_query()?
                     .split(&self.defaults.joiner)
                     .filter(|x| !x.is_empty())
                     .map(|x| x.split(HEADERS_MIDDLE))
@@ -289,19 +590,153 @@ impl<'a> Request<'a> {
 
                 self.set_headers(headers);
             }
+            InjectionPlace::Cookie => {
+                // in case someone searches cookies while sending a valid body - it's usually important to set Content-Type header as well.
+                self.set_body_content_type();
+
+                let query = self.make_query()?;
+
+                // the parameters are added to the user supplied cookies instead of overwriting them
+                match self.headers.get_index_case_insensitive("cookie") {
+                    Some(index) => {
+                        let cookies = self.headers[index].1.trim_end().trim_end_matches(';');
+                        self.headers[index].1 = if cookies.is_empty() {
+                            query
+                        } else if query.is_empty() {
+                            cookies.to_string()
+                        } else {
+                            format!("{}{}{}", cookies, self.defaults.joiner, query)
+                        };
+                    }
+                    None if !query.is_empty() => self.set_header("Cookie".to_string(), query),
+                    None => {}
+                }
+            }
+        }
+
+        // a random parameter per request, so requests don't hit the cache
+        if self.defaults.cache_buster {
+            // the fragment isn't sent to the server, so the parameter goes before it
+            let end = self.path.find('#').unwrap_or(self.path.len());
+            let separator = if self.path[..end].contains('?') {
+                '&'
+            } else {
+                '?'
+            };
+            self.path.insert_str(
+                end,
+                &format!(
+                    "{}{}={}",
+                    separator,
+                    random_line(VALUE_LENGTH),
+                    random_line(VALUE_LENGTH)
+                ),
+            );
+        }
+
+        // the override wins over both the automatically detected and the user supplied Content-Type
+        if let Some(content_type) = &self.defaults.content_type_override {
+            self.headers
+                .retain(|(k, _)| !k.eq_ignore_ascii_case("content-type"));
+            self.headers
+                .push(("Content-Type".to_string(), content_type.to_owned()));
+        }
+
+        Ok(())
+    }
+
+    /// sets the automatically detected Content-Type unless the user supplied one or disabled the detection
+    fn set_auto_content_type(&mut self, content_type: &str) {
+        if !self.defaults.disable_auto_content_type
+            && self.defaults.content_type_override.is_none()
+            && self
+                .defaults
+                .custom_headers
+                .get_index_case_insensitive("content-type")
+                .is_none()
+        {
+            self.set_header("Content-Type", content_type);
+        }
+    }
+
+    /// sets Content-Type according to the body that isn't used for the injection
+    fn set_body_content_type(&mut self) {
+        if !self.defaults.method.eq_ignore_ascii_case("GET")
+            && !self.defaults.method.eq_ignore_ascii_case("HEAD")
+            && !self.body.is_empty()
+        {
+            if self.body.starts_with('{') || self.body.starts_with('[') {
+                self.set_auto_content_type("application/json");
+            } else {
+                self.set_auto_content_type("application/x-www-form-urlencoded");
+            }
         }
     }
 
     pub async fn send_by(self, clients: &Client) -> Result<Response<'a>, Box<dyn Error>> {
+        if let Some(block_detector) = &self.defaults.block_detector {
+            if block_detector.is_blocked() {
+                Err(BLOCKED_ERROR)?
+            }
+        }
+
         match self.clone().request(clients).await {
+            // 502/503/504 from gateways are usually transient
+            Ok(val) if self.defaults.retry_on_code.contains(&val.code) => {
+                self.retry_delay(&format!("code {}", val.code)).await?;
+                Ok(self.clone().request(clients).await?)
+            }
+            // some gateways respond with "please retry" pages and 200 codes
+            Ok(val)
+                if self
+                    .defaults
+                    .retry_on_match
+                    .as_ref()
+                    .is_some_and(|x| x.is_match(&val.text)) =>
+            {
+                self.retry_delay("the response matches --retry-on-match")
+                    .await?;
+                Ok(self.clone().request(clients).await?)
+            }
             Ok(val) => Ok(val),
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+            Err(err) => {
+                // fail fast on errors that won't disappear on retry
+                let class = ErrorClass::of(err.as_ref());
+                if !self.defaults.retry_on.contains(&class) {
+                    log::debug!("{:?} error isn't retried: {}", class, err);
+                    return Err(err);
+                }
+
+                self.retry_delay(&err.to_string()).await?;
                 Ok(self.clone().request(clients).await?)
             }
         }
     }
 
+    /// waits 10 seconds before repeating the request.
+    /// Returns BLOCKED_ERROR without waiting till the end in case the scan is stopped meanwhile
+    async fn retry_delay(&self, reason: &str) -> Result<(), Box<dyn Error>> {
+        log::warn!(
+            "{} {} failed ({}), retrying in 10 seconds",
+            self.defaults.method,
+            self.url(),
+            reason
+        );
+
+        let sleep = tokio::time::sleep(Duration::from_secs(10));
+
+        match &self.defaults.block_detector {
+            Some(block_detector) => tokio::select! {
+                _ = sleep => Ok(()),
+                _ = block_detector.blocked() => Err(BLOCKED_ERROR)?,
+            },
+            None => {
+                sleep.await;
+                Ok(())
+            }
+        }
+    }
+
     // we need to somehow impl Send and Sync for error (for using send() within async recursive func)
     // therefore we are wrapping the original call to send()
     // not a good way tho, maybe someone can suggest a better one
@@ -317,109 +752,425 @@ impl<'a> Request<'a> {
         self.send_by(dc).await
     }
 
-    async fn request(mut self, client: &Client) -> Result<Response<'a>, reqwest::Error> {
-        self.prepare();
+    /// returns the Authorization header value for the request that got 401.
+    /// Either the server sent a new digest challenge (there was no challenge before or the nonce became stale)
+    /// or the token expired and needs to be refreshed
+    async fn retry_authorization(
+        &self,
+        headers: &HeaderMap,
+        token_generation: usize,
+    ) -> Option<HeaderValue> {
+        if let Some(digest_auth) = &self.defaults.digest_auth {
+            if let Some(val) =
+                digest_auth.answer_challenge(headers, &self.defaults.method, &self.path)
+            {
+                return Some(val);
+            }
+        }
+
+        let token_refresher = self.defaults.token_refresher.as_ref()?;
+
+        let token = match token_refresher.refresh(token_generation).await {
+            Ok(val) => val,
+            Err(err) => {
+                log::error!("{}", err);
+                return None;
+            }
+        };
+
+        HeaderValue::from_str(&format!("Bearer {}", token)).ok()
+    }
+
+    async fn request(mut self, client: &Client) -> Result<Response<'a>, Box<dyn Error>> {
+        self.prepare()?;
 
         let mut request = http::Request::builder()
             .method(self.defaults.method.as_str())
-            .uri(self.url());
+            .uri(self.connection_url());
+
+        // the refreshed token replaces the user supplied Authorization header
+        let (token, token_generation) = match &self.defaults.token_refresher {
+            Some(token_refresher) => token_refresher.token(),
+            None => (None, 0),
+        };
 
-        for (k, v) in &self.headers {
+        // otherwise there can be a few Host headers in case the user supplied them in different cases
+        let host_index = self.host_header_index();
+
+        for (i, (k, v)) in self.headers.iter().enumerate() {
+            if token.is_some() && k.eq_ignore_ascii_case("authorization") {
+                continue;
+            }
+            if k.eq_ignore_ascii_case("host") && Some(i) != host_index {
+                continue;
+            }
             request = request.header(k, v)
         }
 
-        let request = request.body(self.body.to_owned()).unwrap();
+        if let Some(token) = &token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
 
-        tokio::time::sleep(self.defaults.delay).await;
+        // reuse the last digest challenge
+        if let Some(digest_auth) = &self.defaults.digest_auth {
+            if let Some(val) = digest_auth.authorization(&self.defaults.method, &self.path) {
+                request = request.header("Authorization", val);
+            }
+        }
 
-        let reqwest_req = reqwest::Request::try_from(request).unwrap();
+        // the body is compressed after the injection, so parameters are within the compressed data as well
+        let body = if self.defaults.gzip_body {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            // writing to Vec can't fail
+            encoder.write_all(self.body.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            self.body.as_bytes().to_vec()
+        };
 
-        let start = Instant::now();
+        // fails on invalid characters within the url or headers
+        let mut request = request.body(body)?;
 
-        let res = client.execute(reqwest_req).await?;
+        // the signature is computed over the final request, so the signed headers replace the existing ones
+        if let Some(signer) = &self.defaults.signer {
+            for (k, v) in signer.sign(&request)? {
+                request.headers_mut().insert(
+                    HeaderName::from_bytes(k.as_bytes())?,
+                    HeaderValue::from_str(&v)?,
+                );
 
-        let duration = start.elapsed();
+                self.headers.retain(|(x, _)| !x.eq_ignore_ascii_case(&k));
+                self.set_header(k, v);
+            }
+        }
 
-        let mut headers: Vec<(String, String)> = Vec::new();
+        tokio::time::sleep(self.defaults.jittered_delay()).await;
 
-        for (k, v) in res.headers() {
-            let k = k.to_string();
+        // the permits are released after the body is read
+        let permit = match &self.defaults.semaphore {
+            // the semaphore can only fail in case it was closed, so we are just ignoring it in this case
+            Some(semaphore) => semaphore.acquire().await.ok(),
+            None => None,
+        };
 
-            // sometimes conversion may fail
-            let v = match v.to_str() {
-                Ok(val) => val,
-                Err(_) => {
-                    log::debug!("Unable to parse {} header. The value is {:?}", k, v);
-                    ""
-                }
+        let host_permit = match &self.defaults.host_limiter {
+            Some(host_limiter) => {
+                let (host, port) = self.defaults.connection_target();
+                host_limiter.acquire(host, port).await
             }
-            .to_string();
+            None => None,
+        };
 
-            headers.push((k, v));
+        // the delay above is applied on top of the rate limit
+        if let Some(rate_limiter) = &self.defaults.rate_limiter {
+            rate_limiter.acquire().await;
         }
 
+        if self.defaults.no_content_length || self.defaults.request_line.is_some() {
+            let request_line = match &self.defaults.request_line {
+                Some(val) => val.replace("{{path}}", &self.path),
+                None => format!("{} {} HTTP/1.1", self.defaults.method, self.path),
+            };
+
+            let start = Instant::now();
+            let res = raw::send(
+                &request,
+                &request_line,
+                !self.defaults.no_content_length,
+                if self.defaults.request_timeout.is_zero() {
+                    self.defaults.timeout
+                } else {
+                    self.defaults.request_timeout
+                },
+            )
+            .await?;
+
+            let raw_request = if self.defaults.exact_requests {
+                Some(res.request)
+            } else {
+                None
+            };
+
+            return Ok(self.response(
+                start.elapsed(),
+                res.code,
+                http::Version::HTTP_11,
+                res.headers,
+                res.body,
+                raw_request,
+            ));
+        }
+
+        // the request is serialized only after the response is received, because the http version is unknown before
+        let mut sent = if self.defaults.exact_requests {
+            Some((
+                request.uri().clone(),
+                request.headers().clone(),
+                request.body().clone(),
+            ))
+        } else {
+            None
+        };
+
+        let mut reqwest_req = reqwest::Request::try_from(request).unwrap();
+        if !self.defaults.request_timeout.is_zero() {
+            *reqwest_req.timeout_mut() = Some(self.defaults.request_timeout);
+        }
+
+        // in case the request needs to be repeated with the new digest challenge or the refreshed token
+        let retry_req =
+            if self.defaults.digest_auth.is_some() || self.defaults.token_refresher.is_some() {
+                reqwest_req.try_clone()
+            } else {
+                None
+            };
+
+        let mut start = Instant::now();
+
+        let mut res = match client.execute(reqwest_req).await {
+            Ok(val) => val,
+            Err(err) if is_invalid_version(&err) => Err(HTTP09_ERROR)?,
+            Err(err) => Err(err)?,
+        };
+
+        // the request is repeated with the new Authorization header
+        if let Some(mut retry_req) = retry_req {
+            if res.status() == 401 {
+                if let Some(val) = self
+                    .retry_authorization(res.headers(), token_generation)
+                    .await
+                {
+                    if let Some((_, headers, _)) = &mut sent {
+                        headers.insert(AUTHORIZATION, val.clone());
+                    }
+                    retry_req.headers_mut().insert(AUTHORIZATION, val);
+                    start = Instant::now();
+                    res = client.execute(retry_req).await?;
+                }
+            }
+        }
+
+        let duration = start.elapsed();
+
+        let headers = RequestDefaults::headers_to_vec(res.headers());
         let code = res.status().as_u16();
-        let http_version = Some(res.version());
+        let version = res.version();
+
+        let raw_request = sent.map(|(uri, headers, body)| {
+            wire_request(&self.defaults.method, &uri, headers, &body, version)
+        });
 
         let body_bytes = res.bytes().await?.to_vec();
 
-        let text = if is_binary_content(headers.get_value_case_insensitive("content-type"))
-            && !self.defaults.check_binary
+        // the follow-up request acquires its own permits
+        drop(permit);
+        drop(host_permit);
+
+        // the parameter's effect may show up only on the page the client-side redirect leads to
+        let redirect_url = if self.defaults.follow_client_redirects && code == 200 {
+            client_redirect(&self.url(), &String::from_utf8_lossy(&body_bytes))
+        } else {
+            None
+        };
+
+        let mut response = self.response(duration, code, version, headers, body_bytes, raw_request);
+
+        // the follow-up is sent as a separate request, so it gets the same headers, auth, limits and logs
+        if let Some(url) = redirect_url {
+            let defaults = response
+                .request
+                .as_ref()
+                .unwrap()
+                .defaults
+                .redirected_to(&url)?;
+            let redirected = Box::pin(Request::new(&defaults, Vec::new()).request(client)).await?;
+            response.follow(redirected);
+        }
+
+        Ok(response)
+    }
+
+    /// creates the response from the received data
+    fn response(
+        self,
+        duration: Duration,
+        code: u16,
+        version: http::Version,
+        mut headers: Vec<(String, String)>,
+        body_bytes: Vec<u8>,
+        raw_request: Option<Vec<u8>>,
+    ) -> Response<'a> {
+        let headers_truncated = self.defaults.limit_headers(&mut headers);
+        let content_length = headers
+            .get_value_case_insensitive("content-length")
+            .and_then(|x| x.trim().parse::<usize>().ok());
+
+        if let Some(val) = content_length.filter(|x| *x != body_bytes.len()) {
+            log::debug!(
+                "Content-Length mismatch: declared {}, received {}",
+                val,
+                body_bytes.len()
+            );
+        }
+
+        if let Some(warc) = &self.defaults.warc {
+            if let Err(err) = warc.write(&self, version, code, &headers, &body_bytes, duration) {
+                log::debug!("Unable to write to the warc file: {}", err);
+            }
+        }
+
+        if let Some(har) = &self.defaults.har {
+            har.add(&self, version, code, &headers, &body_bytes, duration);
+        }
+
+        let text = if is_binary_content(
+            headers.get_value_case_insensitive("content-type"),
+            &self.defaults.binary_mimes,
+        ) && !self.defaults.check_binary
         {
             String::new()
         } else {
             String::from_utf8_lossy(&body_bytes).to_string()
         };
 
+        let beautify = self.defaults.beautify;
+        let tls_info = self.defaults.tls_info.clone();
+        let index = self.defaults.responses_count.fetch_add(1, Ordering::SeqCst) + 1;
+
         let mut response = Response {
             code,
             headers,
             time: duration.as_millis(),
             text,
+            beautified_text: None,
             request: Some(self),
             reflected_parameters: HashMap::new(),
-            http_version,
+            http_version: Some(version),
+            tls_info,
+            content_length,
+            body_length: body_bytes.len(),
+            raw_request,
+            index,
+            headers_truncated,
+            error: None,
         };
 
-        response.beautify_body();
+        // only the body is compared, because headers like Date differ every time
+        if let Some(block_detector) = &response.request.as_ref().unwrap().defaults.block_detector {
+            block_detector.observe(response.code, &response.text);
+        }
+
+        if beautify {
+            response.beautify_body();
+        }
         response.add_headers();
 
-        Ok(response)
+        if let Some(http_log) = &response.request.as_ref().unwrap().defaults.http_log {
+            if let Err(err) = http_log.write(&response) {
+                log::debug!("Unable to write to the http log: {}", err);
+            }
+        }
+
+        response
     }
 
     /// the function is used when there was a error during the request
-    pub fn empty_response(mut self) -> Response<'a> {
-        self.prepare();
+    /// the response to the failed request. The error is kept to show why the request failed
+    pub fn empty_response(mut self, error: Option<String>) -> Response<'a> {
+        // in case the query can't be built, the request has already failed with the same error
+        self.prepare().ok();
         Response {
             time: 0,
             code: 0,
             headers: Vec::new(),
             text: String::new(),
+            beautified_text: None,
             reflected_parameters: HashMap::new(),
             request: Some(self),
             http_version: None,
+            tls_info: None,
+            content_length: None,
+            body_length: 0,
+            raw_request: None,
+            index: 0,
+            headers_truncated: false,
+            error,
         }
     }
 
-    pub fn print(&mut self) -> String {
-        self.prepare();
-        self.print_sent()
+    pub fn print(&mut self) -> Result<String, QueryError> {
+        self.prepare()?;
+        Ok(self.print_sent(None))
     }
 
-    pub fn print_sent(&self) -> String {
-        let host = if self.headers.contains_key("Host") {
-            self.headers.get_value("Host").unwrap()
-        } else {
-            self.defaults.host.to_owned()
+    /// the path with the injected parameters
+    pub fn prepared_path(&mut self) -> Result<&str, QueryError> {
+        self.prepare()?;
+        Ok(&self.path)
+    }
+
+    /// headers that are going to be sent (except from the ones added by reqwest itself, like Content-Length)
+    pub fn prepared_headers(&mut self) -> Result<&[(String, String)], QueryError> {
+        self.prepare()?;
+        Ok(&self.headers)
+    }
+
+    /// the body with the injected parameters (before the optional --gzip-body compression)
+    pub fn prepared_body(&mut self) -> Result<&str, QueryError> {
+        self.prepare()?;
+        Ok(&self.body)
+    }
+
+    /// the index of the last Host header. Only this one is sent,
+    /// so the client doesn't add its own one and there are no duplicates
+    fn host_header_index(&self) -> Option<usize> {
+        self.headers
+            .iter()
+            .rposition(|(k, _)| k.eq_ignore_ascii_case("host"))
+    }
+
+    /// the version is taken from the response when available,
+    /// otherwise from the client configuration (HTTP/1.1 in case it's negotiated)
+    pub fn print_sent(&self, version: Option<http::Version>) -> String {
+        let host = match self.host_header_index() {
+            Some(index) => self.headers[index].1.to_owned(),
+            None => self.defaults.host.to_owned(),
         };
 
-        let mut str_req = format!(
-            "{} {} HTTP/1.1\nHost: {}\n",
-            &self.defaults.method, self.path, host
-        );
+        let version = version
+            .or(self.defaults.http_version)
+            .unwrap_or(http::Version::HTTP_11);
+
+        // HTTP/2 has no request line, so pseudo headers are shown instead
+        let mut str_req = if let Some(request_line) = &self.defaults.request_line {
+            format!(
+                "{}\nHost: {}\n",
+                request_line.replace("{{path}}", &self.path),
+                host
+            )
+        } else if version == http::Version::HTTP_2 {
+            format!(
+                ":method: {}\n:path: {}\n:scheme: {}\n:authority: {}\n",
+                &self.defaults.method, self.path, &self.defaults.scheme, host
+            )
+        } else {
+            format!(
+                "{} {} {}\nHost: {}\n",
+                &self.defaults.method,
+                self.path,
+                version_to_str(version),
+                host
+            )
+        };
 
         for (k, v) in self.headers.iter().sorted() {
-            if k != "Host" {
+            if k.eq_ignore_ascii_case("host") {
+                continue;
+            }
+
+            if version == http::Version::HTTP_2 {
+                str_req += &format!("{}: {}\n", k.to_lowercase(), v)
+            } else {
                 str_req += &format!("{}: {}\n", k, v)
             }
         }
@@ -430,30 +1181,193 @@ impl<'a> Request<'a> {
     }
 }
 
+/// reconstructs the request the way hyper writes it: headers in the insertion order
+/// followed by the ones reqwest and hyper add in case they are absent.
+/// HTTP/2 requests are binary, so they are shown in the HTTP/1 format with lowercase headers
+fn wire_request(
+    method: &str,
+    uri: &http::Uri,
+    mut headers: HeaderMap,
+    body: &[u8],
+    version: http::Version,
+) -> Vec<u8> {
+    let is_http2 = version == http::Version::HTTP_2;
+
+    // reqwest's default headers
+    headers
+        .entry(ACCEPT)
+        .or_insert(HeaderValue::from_static("*/*"));
+    if !headers.contains_key(RANGE) {
+        headers
+            .entry(ACCEPT_ENCODING)
+            .or_insert(HeaderValue::from_static("gzip"));
+    }
+
+    // hyper's ones. HTTP/2 uses the :authority pseudo header instead of Host
+    if !is_http2 && !headers.contains_key(HOST) {
+        let host = match (uri.host(), uri.port_u16(), uri.scheme_str()) {
+            (Some(host), Some(443), Some("https")) | (Some(host), Some(80), Some("http")) => {
+                host.to_string()
+            }
+            (Some(host), Some(port), _) => format!("{}:{}", host, port),
+            (Some(host), None, _) => host.to_string(),
+            _ => String::new(),
+        };
+        headers.insert(HOST, HeaderValue::from_str(&host).unwrap());
+    }
+    if !body.is_empty() && !headers.contains_key(CONTENT_LENGTH) {
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+    }
+
+    // hyper sends HTTP/1.1 even when the server responds with HTTP/1.0
+    let mut raw_request = format!(
+        "{} {} {}\r\n",
+        method,
+        uri.path_and_query().map(|x| x.as_str()).unwrap_or("/"),
+        if is_http2 { "HTTP/2" } else { "HTTP/1.1" }
+    );
+
+    for (k, v) in headers.iter() {
+        let name = if is_http2 {
+            k.as_str().to_string()
+        } else {
+            title_case(k.as_str())
+        };
+        raw_request += &format!("{}: {}\r\n", name, String::from_utf8_lossy(v.as_bytes()));
+    }
+
+    let mut raw_request = (raw_request + "\r\n").into_bytes();
+    raw_request.extend(body);
+
+    raw_request
+}
+
 impl<'a> RequestDefaults {
+    /// errors from ::new() are boxed ConfigError, so they can be downcasted
     pub fn from_config<S: Into<String>>(
         config: &Config,
         method: S,
         url: S,
     ) -> Result<Self, Box<dyn Error>> {
-        Self::new(
+        Self::from_config_with_client(
+            config,
+            create_client(config, false)?,
+            method,
+            url,
+            config.invert,
+        )
+    }
+
+    /// the same as from_config, but the client isn't created.
+    /// Allows to share a single client (and its connection pool) between many urls.
+    /// invert overrides --invert (the query run of --query-and-body)
+    pub fn from_config_with_client<S: Into<String>>(
+        config: &Config,
+        client: Client,
+        method: S,
+        url: S,
+        invert: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut request_defaults = Self::new(
             method.into().as_str(), //method needs to be set explicitly via .set_method()
             url.into().as_str(),    //as well as url
             config.custom_headers.clone(),
             config.delay,
-            create_client(config, false)?,
+            client,
             config.template.clone(),
             config.joiner.clone(),
             config.encode,
-            config.data_type.clone(),
-            config.invert,
+            config.data_type,
+            invert,
             config.headers_discovery,
+            InjectionOptions {
+                cookie_discovery: config.cookie_discovery,
+                fragment: config.fragment,
+                literal_body: config.literal_body,
+                json_array_entries: config.json_array_entries,
+                json_path: config.json_path.as_deref(),
+            },
             &config.body,
             config.disable_custom_parameters,
             config.check_binary,
-        )
+        )?;
+
+        // per-host headers have a priority over the global ones
+        if let Some(host_headers) = config.host_headers.get(&request_defaults.host) {
+            for (k, v) in host_headers {
+                match request_defaults
+                    .custom_headers
+                    .get_index_case_insensitive(k)
+                {
+                    Some(index) => request_defaults.custom_headers[index].1 = v.to_owned(),
+                    None => request_defaults
+                        .custom_headers
+                        .push((k.to_owned(), v.to_owned())),
+                }
+            }
+        }
+
+        request_defaults.delay_jitter = config.delay_jitter;
+        request_defaults.warc = config.warc.clone();
+        request_defaults.har = config.har.clone();
+        request_defaults.http_log = config.http_log.clone();
+        request_defaults.block_detector = config.block_detector.clone();
+        request_defaults.host_limiter = config.host_limiter.clone();
+        request_defaults.rate_limiter = config.rate_limiter.clone();
+        request_defaults.encode_set = config.encode_set.clone();
+        request_defaults.port_override = config.port;
+        request_defaults.connect_to = config.connect_to.as_ref().map(|(host, port)| {
+            (
+                host.to_owned(),
+                port.unwrap_or_else(|| request_defaults.target_port()),
+            )
+        });
+        request_defaults.gzip_body = config.gzip_body;
+        request_defaults.connection_close = config.connection_close;
+        request_defaults.follow_client_redirects = config.follow_client_redirects;
+        request_defaults.exact_requests = config.exact_requests;
+        request_defaults.http_version = config.http_version;
+        request_defaults.digest_auth = config.digest_auth.clone();
+        request_defaults.basic_auth = config
+            .netrc
+            .as_ref()
+            .and_then(|netrc| netrc.basic_auth(&request_defaults.host));
+        request_defaults.token_refresher = config.token_refresher.clone();
+        request_defaults.signer = config.signer.clone();
+        request_defaults.beautify = config.beautify;
+        request_defaults.content_type_override = config.content_type.clone();
+        request_defaults.disable_auto_content_type = config.disable_auto_content_type;
+        request_defaults.no_content_length = config.no_content_length;
+        // hyper sends CONNECT to the connection target, while the tunnel target is expected within the request line
+        request_defaults.request_line = config.request_line.clone().or_else(|| {
+            request_defaults
+                .method
+                .eq_ignore_ascii_case("CONNECT")
+                .then(|| {
+                    format!(
+                        "{} {}:{} HTTP/1.1",
+                        request_defaults.method,
+                        request_defaults.host,
+                        request_defaults.target_port()
+                    )
+                })
+        });
+        request_defaults.timeout = Duration::from_secs(config.timeout as u64);
+        request_defaults.request_timeout = Duration::from_secs(config.probe_timeout as u64);
+        request_defaults.retry_on = config.retry_on.clone();
+        request_defaults.retry_on_code = config.retry_on_code.clone();
+        request_defaults.cache_buster = config.cache_buster == "always";
+        request_defaults.retry_on_match = config.retry_on_match.clone();
+        request_defaults.match_regex = config.match_regex.clone();
+        request_defaults.binary_mimes = config.binary_mimes.clone();
+        request_defaults.max_response_headers = config.max_response_headers;
+        request_defaults.max_response_headers_size = config.max_response_headers_size;
+        request_defaults.seq = Arc::new(AtomicUsize::new(config.seq_start));
+
+        Ok(request_defaults)
     }
 
+    /// errors are ConfigError, so they can be matched on
     pub fn new<S: Into<String> + From<String> + std::fmt::Debug>(
         method: &str,
         url: &str,
@@ -462,37 +1376,62 @@ impl<'a> RequestDefaults {
         client: Client,
         template: Option<S>,
         joiner: Option<S>,
-        encode: bool,
+        encode: EncodeMode,
         mut data_type: Option<DataType>,
         invert: bool,
         headers_discovery: bool,
+        options: InjectionOptions,
         body: &str,
         disable_custom_parameters: bool,
         check_binary: bool,
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> Result<Self, ConfigError> {
+        let InjectionOptions {
+            cookie_discovery,
+            fragment,
+            literal_body,
+            json_array_entries,
+            json_path,
+        } = options;
+
+        // the method is sent as is, but the decision shouldn't depend on its case
+        let is_body_method = ["POST", "PUT", "PATCH", "DELETE"]
+            .iter()
+            .any(|x| x.eq_ignore_ascii_case(method));
+
+        // CONNECT has no conventional body, so the parameters are sent within headers
+        let headers_discovery = headers_discovery || method.eq_ignore_ascii_case("CONNECT");
+
         let mut injection_place = if headers_discovery {
             InjectionPlace::Headers
-        } else if (method == "POST" || method == "PUT" || method == "PATCH" || method == "DELETE")
-            && !invert
-            || (method != "POST"
-                && method != "PUT"
-                && method != "PATCH"
-                && method != "DELETE"
-                && invert)
-        {
+        } else if cookie_discovery {
+            InjectionPlace::Cookie
+        } else if fragment {
+            InjectionPlace::Fragment
+        } else if is_body_method != invert && !literal_body {
             InjectionPlace::Body
         } else {
             InjectionPlace::Path
         };
 
-        if headers_discovery {
+        if headers_discovery || cookie_discovery {
             data_type = Some(DataType::Headers);
 
-            if custom_headers.iter().any(|x| x.1.contains("%s")) {
+            if headers_discovery && custom_headers.iter().any(|x| x.1.contains("%s")) {
                 injection_place = InjectionPlace::HeaderValue;
             }
         }
 
+        // the raw body is used as the injection place only in case it contains the injection point
+        if data_type == Some(DataType::Raw)
+            && matches!(injection_place, InjectionPlace::Body | InjectionPlace::Path)
+        {
+            injection_place = if body.contains("%s") && !literal_body {
+                InjectionPlace::Body
+            } else {
+                InjectionPlace::Path
+            };
+        }
+
         let data_type = if data_type != Some(DataType::ProbablyJson) {
             data_type
 
@@ -503,26 +1442,62 @@ impl<'a> RequestDefaults {
             && data_type == Some(DataType::ProbablyJson)
         {
             Some(DataType::Json)
-        } else if injection_place == InjectionPlace::Path {
+        } else if injection_place == InjectionPlace::Path
+            || injection_place == InjectionPlace::Fragment
+        {
             Some(DataType::Urlencoded)
         } else {
-            unreachable!()
+            Err(ConfigError::UnsupportedDataType)?
+        };
+
+        // the nested object can be only within a json body
+        let data_type = if json_path.is_some()
+            && data_type.is_none()
+            && injection_place == InjectionPlace::Body
+        {
+            Some(DataType::Json)
+        } else {
+            data_type
         };
 
-        let (guessed_template, guessed_joiner, is_json, data_type) =
-            RequestDefaults::guess_data_format(body, &injection_place, data_type);
+        let (guessed_template, guessed_joiner, data_type) = RequestDefaults::guess_data_format(
+            body,
+            &injection_place,
+            data_type,
+            json_array_entries,
+        )?;
+        // id=%s within the query or the urlencoded body
+        let value_injection = !matches!(
+            data_type,
+            Some(DataType::Json | DataType::Raw | DataType::Xml | DataType::Multipart)
+        ) && match injection_place {
+            InjectionPlace::Path | InjectionPlace::Fragment => url.contains("=%s"),
+            InjectionPlace::Body => body.contains("=%s"),
+            _ => false,
+        };
 
-        let (template, joiner) = (
+        // only the word itself is sent as the value
+        let template = if value_injection {
+            template.or_else(|| Some("%k".to_string().into()))
+        } else {
             template
-                .unwrap_or_else(|| guessed_template.to_string().into())
-                .into(),
-            joiner
-                .unwrap_or_else(|| guessed_joiner.to_string().into())
-                .into()
-                .replace("\\r", "\r")
-                .replace("\\n", "\n"),
+        };
+
+        let (template, joiner) = (
+            unescape(
+                &template
+                    .unwrap_or_else(|| guessed_template.to_string().into())
+                    .into(),
+            ),
+            unescape(
+                &joiner
+                    .unwrap_or_else(|| guessed_joiner.to_string().into())
+                    .into(),
+            ),
         );
 
+        RequestDefaults::check_template(&template, injection_place, value_injection)?;
+
         let url = Url::parse(url)?;
 
         let (path, body) = if let Some(data_type) = data_type {
@@ -533,7 +1508,9 @@ impl<'a> RequestDefaults {
                 &joiner,
                 &injection_place,
                 data_type,
-            )
+                json_array_entries,
+                json_path,
+            )?
         } else {
             // injection within headers
             (
@@ -546,54 +1523,195 @@ impl<'a> RequestDefaults {
             method: method.to_string(),
             scheme: url.scheme().to_string(),
             path,
-            host: url.host().ok_or("Host missing")?.to_string(),
+            host: url.host().ok_or(ConfigError::MissingHost)?.to_string(),
             custom_headers,
-            port: url.port_or_known_default().ok_or("Wrong scheme")?,
+            port: url
+                .port_or_known_default()
+                .ok_or_else(|| ConfigError::UnsupportedScheme(url.scheme().to_string()))?,
             delay,
             client,
             template,
             joiner,
             encode,
-            is_json,
+            encode_set: None,
+            // the injections within headers and cookies don't have the body format
+            data_type: data_type.unwrap_or(DataType::Headers),
             body,
             disable_custom_parameters,
             injection_place,
+            value_injection,
+
+            delay_jitter: Duration::from_millis(0),
 
             amount_of_reflections: 0,
 
             parameters: Vec::new(),
 
             check_binary,
+
+            binary_mimes: BinaryMimes::default(),
+
+            literal_body,
+
+            semaphore: None,
+
+            warc: None,
+
+            har: None,
+
+            http_log: None,
+
+            block_detector: None,
+
+            host_limiter: None,
+
+            rate_limiter: None,
+
+            connect_to: None,
+
+            port_override: None,
+
+            gzip_body: false,
+
+            connection_close: false,
+
+            follow_client_redirects: false,
+
+            exact_requests: false,
+
+            http_version: None,
+
+            digest_auth: None,
+
+            basic_auth: None,
+
+            token_refresher: None,
+
+            signer: None,
+
+            beautify: false,
+
+            content_type_override: None,
+
+            disable_auto_content_type: false,
+
+            no_content_length: false,
+
+            request_line: None,
+
+            timeout: Duration::from_secs(15),
+
+            request_timeout: Duration::ZERO,
+
+            retry_on: vec![ErrorClass::Timeout, ErrorClass::Connect, ErrorClass::Body],
+
+            retry_on_code: Vec::new(),
+
+            cache_buster: false,
+
+            retry_on_match: None,
+
+            match_regex: None,
+
+            max_response_headers: 0,
+
+            max_response_headers_size: 0,
+
+            tls_info: None,
+
+            responses_count: Arc::new(AtomicUsize::new(0)),
+
+            seq: Arc::new(AtomicUsize::new(0)),
         })
     }
 
-    /// returns template, joiner, whether the data is json, DataType if the injection point isn't within headers
+    /// delay ± random(delay_jitter), never below zero
+    pub fn jittered_delay(&self) -> Duration {
+        let jitter = self.delay_jitter.as_millis() as u64;
+        if jitter == 0 {
+            return self.delay;
+        }
+
+        let offset = rand::thread_rng().gen_range(0, jitter * 2 + 1);
+        (self.delay + Duration::from_millis(offset)).saturating_sub(Duration::from_millis(jitter))
+    }
+
+    /// the template needs %k for the parameter name and %v for the value.
+    /// With value injection (id=%s) only the name is sent, so %v isn't required.
+    /// The template for headers is internal and always valid
+    fn check_template(
+        template: &str,
+        injection_place: InjectionPlace,
+        value_injection: bool,
+    ) -> Result<(), QueryError> {
+        if injection_place == InjectionPlace::Headers {
+            return Ok(());
+        }
+
+        // %k alone is enough for json array values (-P "%k" --body '{"arr":[%s]}')
+        let placeholder = if value_injection { "%k" } else { "%k or %v" };
+
+        if template.contains("%k") || !value_injection && template.contains("%v") {
+            Ok(())
+        } else {
+            Err(QueryError::MissingPlaceholder {
+                template: template.to_string(),
+                placeholder,
+            })
+        }
+    }
+
+    /// returns template, joiner and DataType (None in case the injection point is within headers)
     fn guess_data_format(
         body: &str,
         injection_place: &InjectionPlace,
         data_type: Option<DataType>,
-    ) -> (&'a str, &'a str, bool, Option<DataType>) {
-        if data_type.is_some() && data_type != Some(DataType::Headers) {
+        json_array_entries: bool,
+    ) -> Result<(&'a str, &'a str, Option<DataType>), ConfigError> {
+        // every parameter is sent as a separate array entry
+        let json_template = if json_array_entries && body.trim_start().starts_with('[') {
+            "{\"%k\":%v}"
+        } else {
+            "\"%k\":%v"
+        };
+
+        let format = if data_type.is_some() && data_type != Some(DataType::Headers) {
             match data_type {
                 // %v isn't within quotes because not every json value needs to be in quotes
-                Some(DataType::Json) => ("\"%k\":%v", ",", true, Some(DataType::Json)),
-                Some(DataType::Urlencoded) => ("%k=%v", "&", false, Some(DataType::Urlencoded)),
-                _ => unreachable!(),
+                Some(DataType::Json) => (json_template, ",", Some(DataType::Json)),
+                Some(DataType::Urlencoded) => ("%k=%v", "&", Some(DataType::Urlencoded)),
+                Some(DataType::Delimited) => ("%k:%v", "\n", Some(DataType::Delimited)),
+                Some(DataType::Raw) => ("%k=%v", "&", Some(DataType::Raw)),
+                Some(DataType::Xml) => ("<%k>%v</%k>", "", Some(DataType::Xml)),
+                Some(DataType::Multipart) => (
+                    "Content-Disposition: form-data; name=\"%k\"\r\n\r\n%v",
+                    "",
+                    Some(DataType::Multipart),
+                ),
+                _ => Err(ConfigError::UnsupportedDataType)?,
             }
         } else {
             match injection_place {
                 InjectionPlace::Body => {
-                    if body.starts_with('{') {
-                        ("\"%k\":%v", ",", true, Some(DataType::Json))
+                    if body.starts_with('{') || body.starts_with('[') {
+                        (json_template, ",", Some(DataType::Json))
+                    } else if body.starts_with('<') {
+                        // either <?xml ..?> or the root element
+                        ("<%k>%v</%k>", "", Some(DataType::Xml))
                     } else {
-                        ("%k=%v", "&", false, Some(DataType::Urlencoded))
+                        ("%k=%v", "&", Some(DataType::Urlencoded))
                     }
                 }
-                InjectionPlace::HeaderValue => ("%k=%v", ";", false, None),
-                InjectionPlace::Path => ("%k=%v", "&", false, Some(DataType::Urlencoded)),
-                InjectionPlace::Headers => (HEADERS_TEMPLATE, HEADERS_JOINER, false, None),
+                InjectionPlace::HeaderValue => ("%k=%v", ";", None),
+                InjectionPlace::Cookie => ("%k=%v", "; ", None),
+                InjectionPlace::Path | InjectionPlace::Fragment => {
+                    ("%k=%v", "&", Some(DataType::Urlencoded))
+                }
+                InjectionPlace::Headers => (HEADERS_TEMPLATE, HEADERS_JOINER, None),
             }
-        }
+        };
+
+        Ok(format)
     }
 
     /// adds injection points where necessary
@@ -603,20 +1721,49 @@ impl<'a> RequestDefaults {
         joiner: &str,
         injection_place: &InjectionPlace,
         data_type: DataType,
-    ) -> (String, String) {
-        match injection_place {
+        json_array_entries: bool,
+        json_path: Option<&str>,
+    ) -> Result<(String, String), ConfigError> {
+        // nothing is assumed about the format, so the injection point isn't added
+        if data_type == DataType::Raw {
+            if !path.contains("%s") && !body.contains("%s") {
+                Err(ConfigError::MissingInjectionPoint)?
+            }
+
+            return Ok((path.to_string(), body.to_string()));
+        }
+
+        let path_and_body = match injection_place {
             InjectionPlace::Body => {
                 if body.contains("%s") {
                     (path.to_string(), body.to_string())
+                } else if let Some(json_path) = json_path.filter(|_| data_type == DataType::Json) {
+                    (
+                        path.to_string(),
+                        RequestDefaults::fix_json_path(body, json_path)?,
+                    )
                 } else if body.is_empty() {
                     match data_type {
-                        DataType::Urlencoded => (path.to_string(), "%s".to_string()),
+                        DataType::Urlencoded | DataType::Delimited | DataType::Multipart => {
+                            (path.to_string(), "%s".to_string())
+                        }
                         DataType::Json => (path.to_string(), "{%s}".to_string()),
-                        _ => unreachable!(),
+                        DataType::Xml => (path.to_string(), "<root>%s</root>".to_string()),
+                        _ => Err(ConfigError::UnsupportedDataType)?,
                     }
                 } else {
                     match data_type {
                         DataType::Urlencoded => (path.to_string(), format!("{}{}%s", body, joiner)),
+                        // the body may already end with the joiner (usually a newline)
+                        DataType::Delimited => (
+                            path.to_string(),
+                            format!("{}{}%s", body.strip_suffix(joiner).unwrap_or(body), joiner),
+                        ),
+                        DataType::Xml => (path.to_string(), RequestDefaults::fix_xml(body)),
+                        DataType::Json if body.trim_start().starts_with('[') => (
+                            path.to_string(),
+                            RequestDefaults::fix_json_array(body, json_array_entries),
+                        ),
                         DataType::Json => {
                             let mut body = body.to_owned();
                             body.pop(); // remove the last '}'
@@ -627,7 +1774,7 @@ impl<'a> RequestDefaults {
                                 (path.to_string(), format!("{}%s}}", body))
                             }
                         }
-                        _ => unreachable!(),
+                        _ => Err(ConfigError::UnsupportedDataType)?,
                     }
                 }
             }
@@ -643,21 +1790,333 @@ impl<'a> RequestDefaults {
                     (format!("{}%s", path), body.to_string())
                 }
             }
+            InjectionPlace::Fragment => {
+                if path.contains("%s") {
+                    (path.to_string(), body.to_string())
+                } else if path.contains('#') {
+                    (format!("{}{}%s", path, joiner), body.to_string())
+                } else {
+                    (format!("{}#%s", path), body.to_string())
+                }
+            }
             _ => (path.to_string(), body.to_string()),
+        };
+
+        Ok(path_and_body)
+    }
+
+    /// adds the injection point to the end of the root element:
+    /// <root><a>1</a></root> -> <root><a>1</a>%s</root>, <root/> -> <root>%s</root>.
+    /// A synthetic root element is added in case there's none (only the <?xml ..?> declaration)
+    fn fix_xml(body: &str) -> String {
+        let body = body.trim_end();
+
+        if let Some(closing_tag) = body.rfind("</") {
+            return format!("{}%s{}", &body[..closing_tag], &body[closing_tag..]);
+        }
+
+        if let Some(element) = body.strip_suffix("/>") {
+            let name = element[element.rfind('<').map_or(0, |x| x + 1)..]
+                .split_whitespace()
+                .next()
+                .unwrap_or_default();
+            return format!("{}>%s</{}>", element.trim_end(), name);
+        }
+
+        format!("{}<root>%s</root>", body)
+    }
+
+    /// adds the injection point to the top-level json array.
+    /// With json_array_entries parameters are added as separate entries to the end of the array,
+    /// otherwise they are added to the first object within the array (a new object is created if there's none)
+    fn fix_json_array(body: &str, json_array_entries: bool) -> String {
+        let body = body.trim();
+
+        if json_array_entries {
+            // [{"a":1}] -> [{"a":1},%s]
+            let end = body.rfind(']').unwrap_or(body.len());
+            let separator = if body[1..end].trim().is_empty() {
+                ""
+            } else {
+                ","
+            };
+            return format!("{}{}%s{}", &body[..end], separator, &body[end..]);
         }
+
+        let rest = body[1..].trim_start();
+        let first_element = body.len() - rest.len();
+
+        if !rest.starts_with('{') {
+            // [1,2] -> [{%s},1,2]
+            let separator = if rest.starts_with(']') { "" } else { "," };
+            return format!("[{{%s}}{}{}", separator, &body[1..]);
+        }
+
+        match RequestDefaults::json_object_end(rest) {
+            // [{"a":1}] -> [{"a":1,%s}]
+            Some(end) => {
+                let end = first_element + end;
+                let separator = if body[first_element + 1..end].trim().is_empty() {
+                    ""
+                } else {
+                    ","
+                };
+                format!("{}{}%s{}", &body[..end], separator, &body[end..])
+            }
+            // malformed json, the parameters are added to the end of the body
+            None => format!("{}%s", body),
+        }
+    }
+
+    /// adds the injection point to the nested object on the path (user.profile, items.0).
+    /// Missing objects are created: {"a":1} with user.profile -> {"a":1,"user":{"profile":{%s}}}.
+    /// The body is reserialized, so the order of keys may change
+    fn fix_json_path(body: &str, json_path: &str) -> Result<String, ConfigError> {
+        // a key that is replaced with %s after serialization
+        const MARKER: &str = "~x8-injection-point~";
+
+        let mut json: Value = if body.trim().is_empty() {
+            Value::Object(Map::new())
+        } else {
+            serde_json::from_str(body).map_err(|err| {
+                ConfigError::InvalidJsonPath(format!("invalid json body: {}", err))
+            })?
+        };
+
+        let mut value = &mut json;
+        for key in json_path.split('.').filter(|x| !x.is_empty()) {
+            value = match value {
+                Value::Object(object) => object
+                    .entry(key)
+                    .or_insert_with(|| Value::Object(Map::new())),
+                // array elements are referenced by their index
+                Value::Array(array) => match key.parse::<usize>() {
+                    Ok(index) if index < array.len() => &mut array[index],
+                    _ => Err(ConfigError::InvalidJsonPath(format!(
+                        "no array element {}",
+                        key
+                    )))?,
+                },
+                _ => Err(ConfigError::InvalidJsonPath(format!(
+                    "the value before {} isn't an object",
+                    key
+                )))?,
+            };
+        }
+
+        match value {
+            Value::Object(object) => {
+                object.insert(MARKER.to_string(), Value::Null);
+            }
+            _ => Err(ConfigError::InvalidJsonPath(format!(
+                "the value of {} isn't an object",
+                json_path
+            )))?,
+        }
+
+        Ok(json
+            .to_string()
+            .replace(&format!("\"{}\":null", MARKER), "%s"))
+    }
+
+    /// the index of the '}' that closes the object at the beginning of the value
+    fn json_object_end(value: &str) -> Option<usize> {
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+
+        for (i, c) in value.char_indices() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => (),
+                }
+                continue;
+            }
+
+            match c {
+                '"' => in_string = true,
+                '{' | '[' => depth += 1,
+                '}' | ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        None
+    }
+
+    /// defaults for the request to the page the client-side redirect leads to.
+    /// Headers and auth are kept, while the parameters and the body aren't sent
+    pub fn redirected_to(&self, url: &str) -> Result<Self, ConfigError> {
+        let url = Url::parse(url)?;
+        let host = url.host().ok_or(ConfigError::MissingHost)?.to_string();
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| ConfigError::UnsupportedScheme(url.scheme().to_string()))?;
+
+        let mut defaults = self.clone();
+
+        // --connect-to and --port are meant for the original target only
+        if host != self.host || port != self.port {
+            defaults.connect_to = None;
+            defaults.port_override = None;
+        }
+
+        defaults.method = "GET".to_string();
+        defaults.scheme = url.scheme().to_string();
+        defaults.host = host;
+        defaults.port = port;
+        defaults.path = url[url::Position::BeforePath..].to_string();
+        defaults.body = String::new();
+        defaults.parameters = Vec::new();
+        defaults.gzip_body = false;
+        defaults.content_type_override = None;
+        // only one redirect is followed
+        defaults.follow_client_redirects = false;
+
+        if defaults.injection_place == InjectionPlace::Body {
+            defaults.injection_place = InjectionPlace::Path;
+        }
+
+        Ok(defaults)
     }
 
     /// recreates url
     pub fn url(&self) -> String {
-        format!("{}://{}:{}{}", self.scheme, self.host, self.port, self.path)
+        format!(
+            "{}://{}:{}{}",
+            self.scheme,
+            self.host,
+            self.target_port(),
+            self.path
+        )
+    }
+
+    /// port requests are sent to. Differs from the url's port with --port
+    pub fn target_port(&self) -> u16 {
+        self.port_override.unwrap_or(self.port)
+    }
+
+    /// host and port the connection is made to (and the SNI is sent for)
+    pub fn connection_target(&self) -> (&str, u16) {
+        match &self.connect_to {
+            Some((host, port)) => (host, *port),
+            None => (&self.host, self.target_port()),
+        }
+    }
+
+    /// drops the headers beyond --max-response-headers and --max-response-headers-size.
+    /// Returns true in case some headers were dropped
+    fn limit_headers(&self, headers: &mut Vec<(String, String)>) -> bool {
+        let max_count = match self.max_response_headers {
+            0 => usize::MAX,
+            val => val,
+        };
+        let max_size = match self.max_response_headers_size {
+            0 => usize::MAX,
+            val => val,
+        };
+
+        let mut size = 0;
+        let count = headers
+            .iter()
+            .take(max_count)
+            .take_while(|(k, v)| {
+                size += k.len() + v.len();
+                size <= max_size
+            })
+            .count();
+
+        if count == headers.len() {
+            return false;
+        }
+
+        log::debug!(
+            "Too many response headers ({}), only {} are processed",
+            headers.len(),
+            count
+        );
+        headers.truncate(count);
+
+        true
+    }
+
+    fn headers_to_vec(header_map: &HeaderMap) -> Vec<(String, String)> {
+        let mut headers: Vec<(String, String)> = Vec::new();
+
+        for (k, v) in header_map {
+            let k = k.to_string();
+
+            // sometimes conversion may fail
+            let v = match v.to_str() {
+                Ok(val) => val,
+                Err(_) => {
+                    log::debug!("Unable to parse {} header. The value is {:?}", k, v);
+                    ""
+                }
+            }
+            .to_string();
+
+            headers.push((k, v));
+        }
+
+        headers
+    }
+
+    /// names of the parameters within the query of the original url
+    pub fn query_parameters(&self) -> Vec<String> {
+        let query = match self.path.split_once('?') {
+            Some((_, query)) => query.split('#').next().unwrap(),
+            None => return Vec::new(),
+        };
+
+        url::form_urlencoded::parse(query.as_bytes())
+            .map(|(k, _)| k.to_string())
+            .filter(|k| !k.is_empty() && !k.contains("%s"))
+            .unique()
+            .collect()
+    }
+
+    /// host with the port in case it isn't the scheme's default one
+    pub fn authority(&self) -> String {
+        if self.scheme == "https" && self.port == 443 || self.scheme == "http" && self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// the format parameters are sent in
+    pub fn data_type_name(&self) -> &'static str {
+        match self.data_type {
+            DataType::Headers if self.injection_place == InjectionPlace::Headers => "headers",
+            DataType::Headers if self.injection_place == InjectionPlace::Cookie => "cookie",
+            DataType::Headers if self.injection_place == InjectionPlace::HeaderValue => {
+                "header value"
+            }
+            DataType::Json => "json",
+            DataType::Raw => "raw",
+            DataType::Delimited => "delimited",
+            DataType::Xml => "xml",
+            DataType::Multipart => "multipart",
+            _ => "urlencoded",
+        }
     }
 
     /// recreates url without default port
     pub fn url_without_default_port(&self) -> String {
-        let port = if self.port == 443 || self.port == 80 {
+        let port = self.target_port();
+        let port = if port == 443 || port == 80 {
             String::new()
         } else {
-            format!(":{}", self.port)
+            format!(":{}", port)
         };
 
         format!("{}://{}{}{}", self.scheme, self.host, port, self.path)
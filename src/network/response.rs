@@ -3,6 +3,7 @@ use std::{
     error::Error,
     io::{self, Write},
     iter::FromIterator,
+    sync::Arc,
 };
 
 use colored::Colorize;
@@ -13,14 +14,15 @@ use regex::Regex;
 
 use crate::{
     config::{structs::Config, utils::file_writer},
-    diff::diff,
+    diff::{diff, unified_diff},
     runner::utils::ReasonKind,
     utils::{color_id, is_id_important},
 };
 
 use super::{
+    raw::TlsInfo,
     request::Request,
-    utils::{save_request, Headers},
+    utils::{save_request, version_to_str, Headers, ReflectionContext},
 };
 
 #[derive(Debug, Clone, Default)]
@@ -37,6 +39,10 @@ pub struct Response<'a> {
     /// headers + body
     pub text: String,
 
+    /// headers + beautified body for displaying and saving.
+    /// None unless --beautify is used. Diffs are always found within the raw text
+    pub beautified_text: Option<String>,
+
     /// hashmap<parameter, amount of reflections> that fills later with possible reflected parameters
     pub reflected_parameters: HashMap<String, usize>,
 
@@ -46,6 +52,31 @@ pub struct Response<'a> {
 
     /// None only when the request failed
     pub http_version: Option<http::Version>,
+
+    /// negotiated TLS parameters of the host. Collected once per url with --tls-info
+    pub tls_info: Option<Arc<TlsInfo>>,
+
+    /// the value of the Content-Length header
+    /// None in case the header is absent or can't be parsed
+    pub content_length: Option<usize>,
+
+    /// the actual length of the received body (before any modifications)
+    pub body_length: usize,
+
+    /// the request as it was sent on the wire.
+    /// None unless --exact-requests is used
+    pub raw_request: Option<Vec<u8>>,
+
+    /// the sequential number of the response within the url's scan, starting from 1.
+    /// Matches the number within the --http-log entries. 0 for empty responses
+    pub index: usize,
+
+    /// some headers were dropped because of --max-response-headers or --max-response-headers-size
+    pub headers_truncated: bool,
+
+    /// why the request failed (timeout, connection refused, tls errors, ..).
+    /// Set only for empty responses
+    pub error: Option<String>,
 }
 
 //Owo
@@ -61,7 +92,50 @@ pub enum Status {
     Other,
 }
 
+/// the diff that is added when the declared Content-Length doesn't match the body length
+pub const CONTENT_LENGTH_MISMATCH: &str = "content-length mismatch";
+
 impl<'a> Response<'a> {
+    /// whether the declared Content-Length differs from the actual body length.
+    /// HEAD responses are skipped because they don't have the body
+    pub fn is_content_length_mismatch(&self) -> bool {
+        let is_head = self
+            .request
+            .as_ref()
+            .is_some_and(|x| x.defaults.method.eq_ignore_ascii_case("HEAD"));
+
+        !is_head
+            && self
+                .content_length
+                .is_some_and(|content_length| content_length != self.body_length)
+    }
+
+    /// returns the header that tells the response was served from cache (Age > 0 or *-Cache*: HIT)
+    pub fn cache_hit(&self) -> Option<String> {
+        if let Some(age) = self.headers.get_value_case_insensitive("age") {
+            if age.trim().parse::<u64>().is_ok_and(|x| x > 0) {
+                return Some(format!("Age: {}", age));
+            }
+        }
+
+        self.headers
+            .iter()
+            .find(|(k, v)| {
+                k.to_lowercase().contains("cache")
+                    && !k.eq_ignore_ascii_case("cache-control")
+                    && v.to_lowercase().contains("hit")
+            })
+            .map(|(k, v)| format!("{}: {}", k, v))
+    }
+
+    /// whether the body matches --match-regex. False in case the regex isn't provided
+    pub fn matches_custom(&self) -> bool {
+        self.request
+            .as_ref()
+            .and_then(|x| x.defaults.match_regex.as_ref())
+            .is_some_and(|re| re.is_match(&self.text))
+    }
+
     /// count how many times we can see the string in the response
     pub fn count(&self, string: &str) -> usize {
         let re = Regex::new(&format!("(?i){}", string)).unwrap();
@@ -81,8 +155,13 @@ impl<'a> Response<'a> {
             is_code_diff = true
         }
 
+        // the mismatch may indicate truncation, smuggling or compression quirks
+        if self.is_content_length_mismatch() && !initial_response.is_content_length_mismatch() {
+            diffs.push(CONTENT_LENGTH_MISMATCH.to_string());
+        }
+
         // just push every found diff to the vector of diffs
-        for diff in diff(&self.print(), &initial_response.print())? {
+        for diff in diff(&self.print_raw(), &initial_response.print_raw())? {
             if !diffs.contains(&diff) && !old_diffs.contains(&diff) {
                 diffs.push(diff);
             // sometimes returns a few same diffs. They should be considered as well
@@ -100,7 +179,8 @@ impl<'a> Response<'a> {
         Ok((is_code_diff, diffs))
     }
 
-    /// adds new lines where necessary in order to increase accuracy in diffing
+    /// adds new lines where necessary in order to increase readability
+    /// the result is stored separately and the raw text isn't changed
     pub fn beautify_body(&mut self) {
         lazy_static! {
             static ref RE_JSON_WORDS_WITHOUT_QUOTES: Regex =
@@ -113,7 +193,7 @@ impl<'a> Response<'a> {
                 Regex::new(r#"(?P<first>"[\w\.-]*"):(?P<second>(false|null|true)),"#).unwrap();
         }
 
-        self.text = if (self.headers.contains_key("content-type")
+        let beautified_text = if (self.headers.contains_key("content-type")
             && self
                 .headers
                 .get_value_case_insensitive("content-type")
@@ -129,7 +209,9 @@ impl<'a> Response<'a> {
             body.to_string()
         } else {
             self.text.replace('>', ">\n")
-        }
+        };
+
+        self.beautified_text = Some(beautified_text);
     }
 
     /// finds parameters with the different amount of reflections and adds them to self.reflected_parameters
@@ -179,6 +261,106 @@ impl<'a> Response<'a> {
         }
     }
 
+    /// returns the unique contexts of the value's reflections within the response
+    pub fn reflection_contexts(&self, value: &str) -> Vec<ReflectionContext> {
+        let re = Regex::new(&format!("(?i){}", regex::escape(value))).unwrap();
+
+        // the headers are prepended to the body (see add_headers())
+        let body_start = if self.text.starts_with('\n') {
+            1
+        } else {
+            self.text.find("\n\n").map_or(0, |x| x + 2)
+        };
+
+        let is_json = self
+            .headers
+            .get_value_case_insensitive("content-type")
+            .is_some_and(|x| x.contains("json"));
+
+        let mut contexts = Vec::new();
+
+        for found in re.find_iter(&self.text) {
+            let context = if found.start() < body_start {
+                ReflectionContext::Header
+            } else if is_json {
+                ReflectionContext::Json
+            } else {
+                // only ascii characters are lowercased, so the offsets stay the same
+                let before = self.text[body_start..found.start()].to_ascii_lowercase();
+
+                let script_start = before.rfind("<script");
+                let tag_start = before.rfind('<');
+
+                if script_start.is_some() && script_start > before.rfind("</script") {
+                    ReflectionContext::Script
+                } else if tag_start.is_some() && tag_start > before.rfind('>') {
+                    // find the quote the value is within
+                    let mut quote = None;
+                    for c in before[tag_start.unwrap()..].chars() {
+                        match quote {
+                            None if c == '"' || c == '\'' => quote = Some(c),
+                            Some(val) if val == c => quote = None,
+                            _ => (),
+                        }
+                    }
+
+                    match quote {
+                        Some('"') => ReflectionContext::DoubleQuotedAttribute,
+                        Some(_) => ReflectionContext::SingleQuotedAttribute,
+                        None => ReflectionContext::Tag,
+                    }
+                } else {
+                    ReflectionContext::Html
+                }
+            };
+
+            if !contexts.contains(&context) {
+                contexts.push(context);
+            }
+        }
+
+        contexts
+    }
+
+    /// returns parameters whose names appear in the response more times than in the initial one
+    /// while their values aren't reflected. For example, {"unknown_field":"<name>"} errors
+    pub fn acknowledged_parameters(&self, initial_response: &Response) -> Vec<String> {
+        let request = self.request.as_ref().unwrap();
+
+        request
+            .prepared_parameters
+            .iter()
+            .filter(|(k, v)| {
+                !request.non_random_parameters.contains_key(k)
+                    && self.text.matches(k.as_str()).count()
+                        > initial_response.text.matches(k.as_str()).count()
+                    && self.count(v).saturating_sub(initial_response.count(v))
+                        == request.defaults.amount_of_reflections
+            })
+            .map(|(k, _)| k.to_owned())
+            .collect()
+    }
+
+    /// returns parameters with random values reflected within the Location header
+    /// such reflections are reported separately because they usually lead to open redirects
+    pub fn location_reflected_parameters(&self) -> Vec<String> {
+        let location = match self.headers.get_value_case_insensitive("location") {
+            Some(val) => val,
+            None => return Vec::new(),
+        };
+
+        let request = self.request.as_ref().unwrap();
+
+        request
+            .prepared_parameters
+            .iter()
+            .filter(|(k, v)| {
+                !request.non_random_parameters.contains_key(k) && location.contains(v.as_str())
+            })
+            .map(|(k, _)| k.to_owned())
+            .collect()
+    }
+
     /// returns parameters with different amount of reflections and tells whether we need to recheck the remaining parameters
     pub fn proceed_reflected_parameters(&self) -> (Option<&str>, bool) {
         if self.reflected_parameters.is_empty() {
@@ -240,9 +422,30 @@ impl<'a> Response<'a> {
             text += &format!("{}: {}\n", k, v);
         }
 
+        if let Some(beautified_text) = &self.beautified_text {
+            self.beautified_text = Some(text.clone() + "\n" + beautified_text);
+        }
+
         self.text = text + "\n" + &self.text;
     }
 
+    /// replaces the received data with the response to the client-side redirect.
+    /// The request stays the same, because it's the one with the parameters
+    pub(super) fn follow(&mut self, redirected: Response<'_>) {
+        self.time = redirected.time;
+        self.code = redirected.code;
+        self.headers = redirected.headers;
+        self.text = redirected.text;
+        self.beautified_text = redirected.beautified_text;
+        self.http_version = redirected.http_version;
+        self.tls_info = redirected.tls_info;
+        self.content_length = redirected.content_length;
+        self.body_length = redirected.body_length;
+        self.raw_request = redirected.raw_request;
+        self.index = redirected.index;
+        self.headers_truncated = redirected.headers_truncated;
+    }
+
     /// write about found parameter to stdout and save when needed
     pub fn write_and_save(
         &self,
@@ -261,13 +464,23 @@ impl<'a> Response<'a> {
         };
 
         let mut message = match reason_kind {
-            ReasonKind::Code => format!(
-                "{}{}: code {} -> {}",
-                &id_if_important,
-                &parameter,
-                initial_response.code(),
-                self.code(),
-            ),
+            ReasonKind::Code => match &self.error {
+                Some(error) => format!(
+                    "{}{}: code {} -> {} ({})",
+                    &id_if_important,
+                    &parameter,
+                    initial_response.code(),
+                    self.code(),
+                    error
+                ),
+                None => format!(
+                    "{}{}: code {} -> {}",
+                    &id_if_important,
+                    &parameter,
+                    initial_response.code(),
+                    self.code(),
+                ),
+            },
             ReasonKind::Text => format!(
                 "{}{}: page {} -> {} ({})",
                 &id_if_important,
@@ -276,23 +489,71 @@ impl<'a> Response<'a> {
                 self.text.len().to_string().bright_yellow(),
                 diff.unwrap()
             ),
-            ReasonKind::Reflected => format!(
+            ReasonKind::Reflected => match diff {
+                Some(contexts) => format!(
+                    "{}{}: {} ({})",
+                    &id_if_important,
+                    "reflects".bright_blue(),
+                    parameter,
+                    contexts
+                ),
+                None => format!(
+                    "{}{}: {}",
+                    &id_if_important,
+                    "reflects".bright_blue(),
+                    parameter
+                ),
+            },
+            ReasonKind::NotReflected => format!(
                 "{}{}: {}",
                 &id_if_important,
-                "reflects".bright_blue(),
+                "changes reflections".bright_cyan(),
                 parameter
             ),
-            ReasonKind::NotReflected => format!(
+            ReasonKind::LocationReflected => format!(
                 "{}{}: {}",
                 &id_if_important,
-                "changes reflections".bright_cyan(),
+                "reflects in location".bright_magenta(),
+                parameter
+            ),
+            ReasonKind::Acknowledged => format!(
+                "{}{}: {}",
+                &id_if_important,
+                "acknowledged".bright_white(),
+                parameter
+            ),
+            ReasonKind::Custom => format!(
+                "{}{}: {}",
+                &id_if_important,
+                &parameter,
+                diff.unwrap_or_default().bright_green()
+            ),
+            ReasonKind::Protocol => format!(
+                "{}{}: {}",
+                &id_if_important,
+                &parameter,
+                diff.unwrap_or_default().bright_red()
+            ),
+            ReasonKind::Interesting => format!(
+                "{}{}: {}",
+                &id_if_important,
+                "interesting".green(),
+                parameter
+            ),
+            ReasonKind::AuthGated => format!(
+                "{}{}: {}",
+                &id_if_important,
+                "auth-gated".purple(),
                 parameter
             ),
         };
 
         if config.verbose > 0 {
             if !config.save_responses.is_empty() {
-                message += &format!(" [saved to {}]", save_request(config, self, parameter)?);
+                message += &format!(
+                    " [saved to {}]",
+                    save_request(config, self, initial_response, parameter)?
+                );
             }
 
             let _ = file_writer(config, &(message.clone() + "\n"));
@@ -302,7 +563,7 @@ impl<'a> Response<'a> {
                 progress_bar.println(message);
             }
         } else if !config.save_responses.is_empty() {
-            save_request(config, self, parameter)?;
+            save_request(config, self, initial_response, parameter)?;
         }
 
         Ok(())
@@ -370,24 +631,73 @@ impl<'a> Response<'a> {
     }
 
     /// print the whole response
+    /// prints the response with the beautified body when available
     pub fn print(&self) -> String {
+        self.print_text(self.beautified_text.as_ref().unwrap_or(&self.text))
+    }
+
+    /// prints the response with the raw body
+    pub fn print_raw(&self) -> String {
+        self.print_text(&self.text)
+    }
+
+    fn print_text(&self, text: &str) -> String {
         let http_version = match self.http_version {
-            Some(val) => match val {
-                http::Version::HTTP_09 => "HTTP/0.9",
-                http::Version::HTTP_10 => "HTTP/1.0",
-                http::Version::HTTP_11 => "HTTP/1.1",
-                http::Version::HTTP_2 => "HTTP/2",
-                http::Version::HTTP_3 => "HTTP/3",
-                _ => "HTTP/x",
-            },
+            Some(val) => version_to_str(val),
             None => "HTTP/x",
         };
 
-        format!("{} {} \n{}", http_version, self.code, self.text)
+        format!("{} {} \n{}", http_version, self.code, text)
+    }
+
+    /// the changes compared to the initial response.
+    /// Code, length and header changes are summarized at the top, followed by the unified diff of the texts
+    pub fn print_diff(&self, initial_response: &Response) -> Result<String, Box<dyn Error>> {
+        let mut output = String::new();
+
+        if self.code != initial_response.code {
+            output += &format!("code: {} -> {}\n", initial_response.code, self.code);
+        }
+
+        if self.text.len() != initial_response.text.len() {
+            output += &format!(
+                "length: {} -> {}\n",
+                initial_response.text.len(),
+                self.text.len()
+            );
+        }
+
+        for (k, v) in initial_response.headers.iter() {
+            match self.headers.get_value_case_insensitive(k) {
+                Some(val) if &val != v => output += &format!("header {}: {} -> {}\n", k, v, val),
+                Some(_) => (),
+                None => output += &format!("header removed: {}: {}\n", k, v),
+            }
+        }
+
+        for (k, v) in self.headers.iter() {
+            if initial_response
+                .headers
+                .get_value_case_insensitive(k)
+                .is_none()
+            {
+                output += &format!("header added: {}: {}\n", k, v);
+            }
+        }
+
+        output += "--- initial\n+++ found\n";
+        output += &unified_diff(&initial_response.text, &self.text)?;
+
+        Ok(output)
     }
 
     /// print the request and response
     pub fn print_all(&self) -> String {
-        self.request.as_ref().unwrap().print_sent() + "\n\n" + &self.print()
+        let request = match &self.raw_request {
+            Some(val) => String::from_utf8_lossy(val).to_string(),
+            None => self.request.as_ref().unwrap().print_sent(self.http_version),
+        };
+
+        request + "\n\n" + &self.print()
     }
 }
@@ -0,0 +1,201 @@
+use std::{error::Error, fmt::Debug, time::SystemTime};
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use ring::{digest, hmac};
+
+use super::warc::format_date;
+
+/// characters that aren't encoded within the canonical request (unreserved ones)
+const AWS_UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+const AWS_PATH: &AsciiSet = &AWS_UNRESERVED.remove(b'/');
+
+/// computes headers for every request right before it's sent (after the parameters are injected).
+/// Used for APIs that require a signature over the method, path, body, etc
+pub trait RequestSigner: Debug + Send + Sync {
+    /// returns headers to add to the request. They replace the headers with the same names
+    fn sign(
+        &self,
+        request: &http::Request<Vec<u8>>,
+    ) -> Result<Vec<(String, String)>, Box<dyn Error>>;
+}
+
+/// AWS Signature Version 4 (Authorization header, AWS4-HMAC-SHA256)
+#[derive(Debug)]
+pub struct AwsSigV4 {
+    access_key: String,
+    secret_key: String,
+    region: String,
+    service: String,
+    session_token: Option<String>,
+}
+
+impl AwsSigV4 {
+    /// parses access_key:secret_key:region:service[:session_token]
+    pub fn new(value: &str) -> Result<Self, Box<dyn Error>> {
+        let parts: Vec<&str> = value.splitn(5, ':').collect();
+
+        if parts.len() < 4 || parts[..4].iter().any(|x| x.is_empty()) {
+            Err("Unable to parse aws credentials. The format is access_key:secret_key:region:service[:session_token]")?
+        }
+
+        Ok(Self {
+            access_key: parts[0].to_string(),
+            secret_key: parts[1].to_string(),
+            region: parts[2].to_string(),
+            service: parts[3].to_string(),
+            session_token: parts.get(4).map(|x| x.to_string()),
+        })
+    }
+
+    /// signs the request as if it was sent at the given time
+    pub fn sign_at(
+        &self,
+        request: &http::Request<Vec<u8>>,
+        time: SystemTime,
+    ) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        // YYYY-MM-DDThh:mm:ssZ -> YYYYMMDDThhmmssZ
+        let amz_date = format_date(time).replace(&['-', ':'][..], "");
+        let date = &amz_date[..8];
+
+        let uri = request.uri();
+        let is_s3 = self.service == "s3";
+        let body_hash = sha256_hex(request.body());
+
+        let host = match request.headers().get(http::header::HOST) {
+            Some(val) => val.to_str()?.to_string(),
+            None => {
+                let host = uri.host().ok_or("The url doesn't contain the host")?;
+                match (uri.scheme_str(), uri.port_u16()) {
+                    (Some("https"), Some(443)) | (Some("http"), Some(80)) | (_, None) => {
+                        host.to_string()
+                    }
+                    (_, Some(port)) => format!("{}:{}", host, port),
+                }
+            }
+        };
+
+        let mut new_headers = vec![
+            ("Host".to_string(), host),
+            ("X-Amz-Date".to_string(), amz_date.clone()),
+        ];
+        // required only by S3
+        if is_s3 {
+            new_headers.push(("X-Amz-Content-Sha256".to_string(), body_hash.clone()));
+        }
+        if let Some(session_token) = &self.session_token {
+            new_headers.push(("X-Amz-Security-Token".to_string(), session_token.clone()));
+        }
+
+        let mut signed_headers: Vec<(String, String)> = new_headers
+            .iter()
+            .map(|(k, v)| (k.to_lowercase(), v.trim().to_string()))
+            .collect();
+        if let Some(val) = request.headers().get(http::header::CONTENT_TYPE) {
+            signed_headers.push(("content-type".to_string(), val.to_str()?.trim().to_string()));
+        }
+        signed_headers.sort();
+
+        let signed_header_names = signed_headers
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<&str>>()
+            .join(";");
+
+        // every path segment is encoded twice except for S3
+        let path = percent_decode_str(uri.path()).decode_utf8_lossy();
+        let mut path = utf8_percent_encode(&path, AWS_PATH).to_string();
+        if !is_s3 {
+            path = utf8_percent_encode(&path, AWS_PATH).to_string();
+        }
+
+        let mut query: Vec<(String, String)> = uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|x| !x.is_empty())
+            .map(|x| {
+                let (k, v) = x.split_once('=').unwrap_or((x, ""));
+                (aws_encode(k), aws_encode(v))
+            })
+            .collect();
+        query.sort();
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n\n{}\n{}",
+            request.method(),
+            path,
+            query
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<String>>()
+                .join("&"),
+            signed_headers
+                .iter()
+                .map(|(k, v)| format!("{}:{}", k, v))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            signed_header_names,
+            body_hash
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let mut key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date, &self.region, &self.service, "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        new_headers.push((
+            "Authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_header_names, signature
+            ),
+        ));
+
+        Ok(new_headers)
+    }
+}
+
+impl RequestSigner for AwsSigV4 {
+    fn sign(
+        &self,
+        request: &http::Request<Vec<u8>>,
+    ) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        self.sign_at(request, SystemTime::now())
+    }
+}
+
+fn aws_encode(value: &str) -> String {
+    utf8_percent_encode(
+        &percent_decode_str(value).decode_utf8_lossy(),
+        AWS_UNRESERVED,
+    )
+    .to_string()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data)
+        .as_ref()
+        .to_vec()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|x| format!("{:02x}", x)).collect()
+}
@@ -1,53 +1,381 @@
 #[cfg(test)]
 mod tests {
+    use std::{
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    };
     use tokio::time::Duration;
 
-    use crate::network::{
-        request::{Request, RequestDefaults},
-        utils::{is_binary_content, Headers, InjectionPlace},
+    use crate::{
+        config::error::{ConfigError, QueryError},
+        diff::unified_diff,
+        network::{
+            auth::Netrc,
+            request::{InjectionOptions, Request, RequestDefaults},
+            response::Response,
+            signer::AwsSigV4,
+            utils::{
+                ascii_set, client_redirect, is_binary_content, unescape, BinaryMimes, DataType,
+                EncodeMode, Headers, InjectionPlace, ReflectionContext,
+            },
+            warc::format_date,
+        },
     };
 
+    /// the arguments of RequestDefaults::new that differ between the tests
+    struct Args<'a> {
+        method: &'a str,
+        url: &'a str,
+        custom_headers: Vec<(String, String)>,
+        template: Option<&'a str>,
+        joiner: Option<&'a str>,
+        data_type: Option<DataType>,
+        cookie_discovery: bool,
+        literal_body: bool,
+        json_array_entries: bool,
+        json_path: Option<&'a str>,
+        body: &'a str,
+    }
+
+    impl Default for Args<'_> {
+        fn default() -> Self {
+            Self {
+                method: "POST",
+                url: "https://example.com/path",
+                custom_headers: Vec::new(),
+                template: None,
+                joiner: None,
+                data_type: None,
+                cookie_discovery: false,
+                literal_body: false,
+                json_array_entries: false,
+                json_path: None,
+                body: "",
+            }
+        }
+    }
+
+    /// RequestDefaults with the rest of the arguments set to their defaults
+    fn request_defaults(args: Args) -> Result<RequestDefaults, ConfigError> {
+        RequestDefaults::new(
+            args.method,
+            args.url,
+            args.custom_headers,
+            Duration::from_millis(0),
+            Default::default(),
+            args.template.map(String::from),
+            args.joiner.map(String::from),
+            EncodeMode::None,
+            args.data_type,
+            false,
+            false,
+            InjectionOptions {
+                cookie_discovery: args.cookie_discovery,
+                literal_body: args.literal_body,
+                json_array_entries: args.json_array_entries,
+                json_path: args.json_path,
+                ..Default::default()
+            },
+            args.body,
+            false,
+            false,
+        )
+    }
+
+    #[test]
+    fn unescape_sequences() {
+        assert_eq!(unescape(r"a\r\n\t\0b"), "a\r\n\t\0b");
+        assert_eq!(unescape(r"\x01\u00e9\\"), "\x01é\\");
+        assert_eq!(unescape(r"\xZZ\u12\q"), r"\xZZ\u12\q");
+    }
+
+    #[test]
+    fn netrc_credentials() {
+        let netrc = Netrc::parse(
+            "# comment\nmachine example.com login user password pass\n\
+             machine other.com\n  # comment\n  login a\n  password p#ss\ndefault login anon password x",
+        );
+
+        assert_eq!(
+            netrc.basic_auth("EXAMPLE.com").unwrap(),
+            "Basic dXNlcjpwYXNz"
+        );
+        assert_eq!(netrc.basic_auth("other.com").unwrap(), "Basic YTpwI3Nz");
+        assert_eq!(netrc.basic_auth("unknown.com").unwrap(), "Basic YW5vbjp4");
+        assert!(Netrc::parse("machine example.com login a password b")
+            .basic_auth("unknown.com")
+            .is_none());
+    }
+
+    #[test]
+    fn client_redirect_detection() {
+        let base = "https://example.com/a/b?x=1";
+
+        assert_eq!(
+            client_redirect(
+                base,
+                r#"<META content="0; URL='/next?y=2'" http-equiv="refresh">"#
+            )
+            .unwrap(),
+            "https://example.com/next?y=2"
+        );
+        assert_eq!(
+            client_redirect(base, "<script>window.location.href = 'c';</script>").unwrap(),
+            "https://example.com/a/c"
+        );
+        assert_eq!(
+            client_redirect(base, r#"location.replace("https://other.com/")"#).unwrap(),
+            "https://other.com/"
+        );
+        assert!(client_redirect(base, "location = 'javascript:void(0)'").is_none());
+        assert!(client_redirect(base, "<p>no redirects</p>").is_none());
+    }
+
     #[test]
     fn check_is_binary_content() {
-        assert!(is_binary_content(Some("application/pdf".to_string())));
-        assert!(is_binary_content(Some("something/zip".to_string())));
-        assert!(is_binary_content(Some("image/png".to_string())));
-        assert!(is_binary_content(Some("image/something".to_string())));
+        let mimes = BinaryMimes::default();
+        assert!(is_binary_content(
+            Some("application/pdf".to_string()),
+            &mimes
+        ));
+        assert!(is_binary_content(Some("something/zip".to_string()), &mimes));
+        assert!(is_binary_content(Some("image/png".to_string()), &mimes));
+        assert!(is_binary_content(
+            Some("image/something".to_string()),
+            &mimes
+        ));
+
+        assert!(!is_binary_content(
+            Some("application/json".to_string()),
+            &mimes
+        ));
+        assert!(!is_binary_content(
+            Some("application/html".to_string()),
+            &mimes
+        ));
+        assert!(!is_binary_content(
+            Some("application/wasm".to_string()),
+            &mimes
+        ));
+
+        let mimes = BinaryMimes::new(&["wasm".to_string()], false).unwrap();
+        assert!(is_binary_content(
+            Some("application/wasm".to_string()),
+            &mimes
+        ));
+        assert!(is_binary_content(Some("image/png".to_string()), &mimes));
 
-        assert!(!is_binary_content(Some("application/json".to_string())));
-        assert!(!is_binary_content(Some("application/html".to_string())));
+        let mimes = BinaryMimes::new(&[], true).unwrap();
+        assert!(is_binary_content(
+            Some("application/x-protobuf".to_string()),
+            &mimes
+        ));
+        assert!(!is_binary_content(
+            Some("application/json; charset=utf-8".to_string()),
+            &mimes
+        ));
+    }
+
+    #[test]
+    fn unified_diff_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm";
+
+        assert_eq!(
+            unified_diff(old, new).unwrap(),
+            "@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n@@ -10,3 +10,4 @@\n j\n k\n l\n+m\n"
+        );
+        assert_eq!(unified_diff(old, old).unwrap(), "");
+        assert_eq!(unified_diff("", "a").unwrap(), "@@ -0,0 +1,1 @@\n+a\n");
+    }
+
+    #[test]
+    fn reflection_context_detection() {
+        let mut response = Response {
+            headers: vec![("X-Value".to_string(), "v1".to_string())],
+            text:
+                "<p>v2</p><input value=\"v3\" title='v4' name=v5><script>var a = 'v6';</script>v7"
+                    .to_string(),
+            ..Default::default()
+        };
+        response.add_headers();
+
+        let contexts: Vec<ReflectionContext> = (1..=7)
+            .map(|x| response.reflection_contexts(&format!("v{}", x))[0])
+            .collect();
+
+        assert_eq!(
+            contexts,
+            vec![
+                ReflectionContext::Header,
+                ReflectionContext::Html,
+                ReflectionContext::DoubleQuotedAttribute,
+                ReflectionContext::SingleQuotedAttribute,
+                ReflectionContext::Tag,
+                ReflectionContext::Script,
+                ReflectionContext::Html,
+            ]
+        );
+    }
+
+    #[test]
+    fn warc_date_formatting() {
+        assert_eq!(format_date(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_date(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            "2023-11-14T22:13:20Z"
+        );
+        assert_eq!(
+            format_date(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+        assert!(format_date(SystemTime::now()).ends_with('Z'));
+    }
+
+    #[test]
+    fn aws_sigv4_signature() {
+        // get-vanilla from the AWS Signature Version 4 test suite
+        let signer =
+            AwsSigV4::new("AKIDEXAMPLE:wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY:us-east-1:service")
+                .unwrap();
+        let request = http::Request::builder()
+            .method("GET")
+            .uri("https://example.amazonaws.com/")
+            .body(Vec::new())
+            .unwrap();
+
+        let headers = signer
+            .sign_at(&request, UNIX_EPOCH + Duration::from_secs(1_440_938_160))
+            .unwrap();
+
+        assert_eq!(headers.get_value("X-Amz-Date").unwrap(), "20150830T123600Z");
+        assert_eq!(
+            headers.get_value("Authorization").unwrap(),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+            SignedHeaders=host;x-amz-date, \
+            Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
     }
 
     #[test]
     fn query_creation() {
         let mut l = RequestDefaults::default();
-        l.template = "%k=payload".to_string();
+        l.template = "%k=%v".to_string();
         l.joiner = "&".to_string();
-        let parameters = vec!["test1".to_string()];
+        let parameters = vec!["test1=payload".to_string()];
         let mut request = Request::new(&l, parameters);
-        request.prepare();
+        request.prepare().unwrap();
 
-        assert_eq!(request.make_query(), "test1=payload");
+        assert_eq!(request.make_query().unwrap(), "test1=payload");
+    }
+
+    #[test]
+    fn authority_default_port() {
+        let authority = |scheme: &str, port: u16| {
+            RequestDefaults {
+                scheme: scheme.to_string(),
+                host: "example.com".to_string(),
+                port,
+                ..Default::default()
+            }
+            .authority()
+        };
+
+        assert_eq!(authority("https", 443), "example.com");
+        assert_eq!(authority("http", 80), "example.com");
+        assert_eq!(authority("http", 443), "example.com:443");
+        assert_eq!(authority("https", 80), "example.com:80");
+        assert_eq!(authority("https", 8443), "example.com:8443");
+    }
+
+    #[test]
+    fn query_template_without_placeholder() {
+        let l = RequestDefaults {
+            template: "key:%c".to_string(),
+            joiner: "&".to_string(),
+            ..Default::default()
+        };
+        let mut request = Request::new(&l, vec!["test1".to_string()]);
+
+        match request.prepare() {
+            Err(QueryError::MissingPlaceholder { placeholder, .. }) => {
+                assert_eq!(placeholder, "%k or %v")
+            }
+            _ => panic!("the template without placeholders should be rejected"),
+        }
+
+        let l = RequestDefaults {
+            template: "\"%k\"".to_string(),
+            joiner: ", ".to_string(),
+            ..Default::default()
+        };
+        let mut request = Request::new(&l, vec!["test1".to_string()]);
+        request.prepare().unwrap();
+        assert_eq!(request.make_query().unwrap(), "\"test1\"");
+    }
+
+    #[test]
+    fn encoded_parameters_are_decoded() {
+        let l = RequestDefaults {
+            template: "%k=%v".to_string(),
+            joiner: "&".to_string(),
+            encode: EncodeMode::Single,
+            ..Default::default()
+        };
+
+        let mut request = Request::new(&l, vec!["a%5B0%5D=b%20c".to_string()]);
+        request.prepare().unwrap();
+
+        assert_eq!(
+            request.non_random_parameters,
+            vec![("a[0]".to_string(), "b c".to_string())]
+        );
+        // encoded once, not "b%2520c"
+        assert_eq!(request.make_query().unwrap(), "a[0]%3Db%20c");
+
+        let l = RequestDefaults {
+            encode: EncodeMode::Double,
+            ..l
+        };
+        let mut request = Request::new(&l, vec!["a=b".to_string()]);
+        request.prepare().unwrap();
+        assert_eq!(request.make_query().unwrap(), "a%253Db");
+
+        let l = RequestDefaults {
+            encode: EncodeMode::Single,
+            encode_set: Some(Arc::new(ascii_set("=(").unwrap())),
+            ..l
+        };
+        let mut request = Request::new(&l, vec!["a/(b)=c".to_string()]);
+        request.prepare().unwrap();
+        assert_eq!(request.make_query().unwrap(), "a/%28b)%3Dc");
+        assert!(ascii_set("é").is_none());
+    }
+
+    #[test]
+    fn seq_template() {
+        let l = RequestDefaults {
+            body: "{{seq}}-{{seq}}".to_string(),
+            ..Default::default()
+        };
+
+        let mut request = Request::new(&l, Vec::new());
+        request.prepare().unwrap();
+        assert_eq!(request.body, "0-0");
+
+        let mut request = Request::new(&l, Vec::new());
+        request.prepare().unwrap();
+        assert_eq!(request.body, "1-1");
     }
 
     #[test]
     fn request_defaults_generation() {
-        let defaults = RequestDefaults::new::<String>(
-            "GET",
-            "https://example.com:8443/path",
-            Vec::from([("X-Header".to_string(), "Value".to_string())]),
-            Duration::from_millis(0),
-            Default::default(),
-            None,
-            None,
-            false,
-            None,
-            false,
-            false,
-            "",
-            false,
-            false,
-        )
+        let defaults = request_defaults(Args {
+            method: "GET",
+            url: "https://example.com:8443/path",
+            custom_headers: Vec::from([("X-Header".to_string(), "Value".to_string())]),
+            ..Default::default()
+        })
         .unwrap();
 
         assert_eq!(defaults.scheme, "https");
@@ -63,28 +391,248 @@ mod tests {
         assert_eq!(defaults.injection_place, InjectionPlace::Path);
     }
 
+    #[test]
+    fn request_defaults_errors() {
+        let new = |url: &str| {
+            request_defaults(Args {
+                method: "GET",
+                url,
+                ..Default::default()
+            })
+        };
+
+        assert!(matches!(new("example.com"), Err(ConfigError::UrlParse(_))));
+        assert!(matches!(
+            new("ftp2://example.com/"),
+            Err(ConfigError::UnsupportedScheme(scheme)) if scheme == "ftp2"
+        ));
+        assert!(matches!(
+            new("data:text/plain,x"),
+            Err(ConfigError::MissingHost)
+        ));
+    }
+
     #[test]
     fn json_request_body_generation() {
-        let defaults = RequestDefaults::new::<String>(
-            "POST",
-            "https://example.com:8443/path",
-            Vec::from([("X-Header".to_string(), "Value".to_string())]),
-            Duration::from_millis(0),
-            Default::default(),
-            None,
-            None,
-            false,
-            None,
-            false,
-            false,
-            "{\"something\":1}",
-            false,
-            false,
-        )
+        let defaults = request_defaults(Args {
+            url: "https://example.com:8443/path",
+            custom_headers: Vec::from([("X-Header".to_string(), "Value".to_string())]),
+            body: "{\"something\":1}",
+            ..Default::default()
+        })
         .unwrap();
 
-        assert!(defaults.is_json);
+        assert_eq!(defaults.data_type, DataType::Json);
         assert_eq!(defaults.body, "{\"something\":1, %s}");
         assert_eq!(defaults.template, "\"%k\": %v");
     }
+
+    #[test]
+    fn json_path_body_generation() {
+        let new = |body: &str, json_path: &str| {
+            request_defaults(Args {
+                json_path: Some(json_path),
+                body,
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            new(r#"{"a":1,"user":{"profile":{"name":"b"}}}"#, "user.profile")
+                .unwrap()
+                .body,
+            r#"{"a":1,"user":{"profile":{"name":"b",%s}}}"#
+        );
+        assert_eq!(
+            new(r#"{"a":1}"#, "user.profile").unwrap().body,
+            r#"{"a":1,"user":{"profile":{%s}}}"#
+        );
+        assert_eq!(
+            new(r#"{"items":[{"a":1}]}"#, "items.0").unwrap().body,
+            r#"{"items":[{"a":1,%s}]}"#
+        );
+
+        let defaults = new("", "user").unwrap();
+        assert_eq!(defaults.data_type, DataType::Json);
+        assert_eq!(defaults.body, r#"{"user":{%s}}"#);
+
+        assert!(matches!(
+            new(r#"{"a":1}"#, "a.b"),
+            Err(ConfigError::InvalidJsonPath(_))
+        ));
+    }
+
+    #[test]
+    fn json_array_body_generation() {
+        let new = |body: &str, json_array_entries: bool| {
+            request_defaults(Args {
+                json_array_entries,
+                body,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        let defaults = new("[{\"a\":\"}\"},{\"b\":2}]", false);
+        assert_eq!(defaults.data_type, DataType::Json);
+        assert_eq!(defaults.body, "[{\"a\":\"}\",%s},{\"b\":2}]");
+        assert_eq!(defaults.template, "\"%k\":%v");
+
+        assert_eq!(new("[{}]", false).body, "[{%s}]");
+        assert_eq!(new("[1,2]", false).body, "[{%s},1,2]");
+
+        let defaults = new("[{\"a\":1}]", true);
+        assert_eq!(defaults.body, "[{\"a\":1},%s]");
+        assert_eq!(defaults.template, "{\"%k\":%v}");
+
+        let mut request = Request::new(&defaults, vec!["x".to_string(), "y".to_string()]);
+        request.prepare().unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(&request.body).is_ok());
+    }
+
+    #[test]
+    fn delimited_body_generation() {
+        let defaults = request_defaults(Args {
+            data_type: Some(DataType::Delimited),
+            body: "a:1\n",
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_ne!(defaults.data_type, DataType::Json);
+        assert_eq!(defaults.body, "a:1\n%s");
+        assert_eq!(defaults.template, "%k:%v");
+        assert_eq!(defaults.joiner, "\n");
+
+        let mut request = Request::new(&defaults, vec!["x".to_string(), "y".to_string()]);
+        request.prepare().unwrap();
+        let lines: Vec<&str> = request.body.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("x:") && lines[2].starts_with("y:"));
+    }
+
+    #[test]
+    fn xml_body_generation() {
+        let defaults = request_defaults(Args {
+            body: "<?xml version=\"1.0\"?><root><a>1</a></root>",
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(defaults.data_type, DataType::Xml);
+        assert_eq!(
+            defaults.body,
+            "<?xml version=\"1.0\"?><root><a>1</a>%s</root>"
+        );
+        assert_eq!(defaults.template, "<%k>%v</%k>");
+
+        let mut request = Request::new(&defaults, vec!["x=1".to_string()]);
+        request.prepare().unwrap();
+        assert!(request.body.ends_with("<a>1</a><x>1</x></root>"));
+        assert!(request
+            .headers
+            .contains(&("Content-Type".to_string(), "application/xml".to_string())));
+    }
+
+    #[test]
+    fn multipart_body_generation() {
+        let defaults = request_defaults(Args {
+            data_type: Some(DataType::Multipart),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut request = Request::new(&defaults, vec!["x=1".to_string()]);
+        request.prepare().unwrap();
+        let boundary = request.boundary.clone();
+        assert_eq!(
+            request.body,
+            format!(
+                "--{0}\r\nContent-Disposition: form-data; name=\"x\"\r\n\r\n1\r\n--{0}--\r\n",
+                boundary
+            )
+        );
+        assert!(request.headers.contains(&(
+            "Content-Type".to_string(),
+            format!("multipart/form-data; boundary={}", boundary)
+        )));
+        assert_ne!(Request::new(&defaults, Vec::new()).boundary, boundary);
+    }
+
+    #[test]
+    fn cookie_discovery_merges_cookies() {
+        let defaults = request_defaults(Args {
+            method: "GET",
+            custom_headers: Vec::from([("Cookie".to_string(), "session=abc;".to_string())]),
+            cookie_discovery: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(defaults.injection_place, InjectionPlace::Cookie);
+
+        let mut request = Request::new(&defaults, vec!["x=1".to_string(), "y=2".to_string()]);
+        request.prepare().unwrap();
+        assert_eq!(
+            request.headers,
+            vec![("Cookie".to_string(), "session=abc; x=1; y=2".to_string())]
+        );
+    }
+
+    #[test]
+    fn raw_body_generation() {
+        let raw_defaults = |body: &str| {
+            request_defaults(Args {
+                template: Some("%k: %v"),
+                joiner: Some(" "),
+                data_type: Some(DataType::Raw),
+                body,
+                ..Default::default()
+            })
+        };
+
+        let defaults = raw_defaults("msg { %s }").unwrap();
+        assert_eq!(defaults.injection_place, InjectionPlace::Body);
+        assert_eq!(defaults.body, "msg { %s }");
+
+        let mut request = Request::new(&defaults, vec!["x=1".to_string()]);
+        request.prepare().unwrap();
+        assert_eq!(request.body, "msg { x: 1 }");
+        assert!(request.headers.is_empty());
+
+        assert!(matches!(
+            raw_defaults("msg {}"),
+            Err(ConfigError::MissingInjectionPoint)
+        ));
+    }
+
+    #[test]
+    fn lowercase_method_injection_place() {
+        let defaults = request_defaults(Args {
+            method: "post",
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(defaults.method, "post");
+        assert_eq!(defaults.injection_place, InjectionPlace::Body);
+    }
+
+    #[test]
+    fn literal_body_is_not_injected() {
+        let defaults = request_defaults(Args {
+            literal_body: true,
+            body: "a=%s&b={{random}}",
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(defaults.injection_place, InjectionPlace::Path);
+        assert_eq!(defaults.path, "/path?%s");
+
+        let mut request = Request::new(&defaults, vec!["param".to_string()]);
+        request.prepare().unwrap();
+        assert_eq!(request.body, "a=%s&b={{random}}");
+        assert!(request.path.starts_with("/path?param="));
+    }
 }
@@ -1,14 +1,15 @@
-use std::{error::Error, time::Duration};
+use std::{env, error::Error, fs, io, time::Duration};
 
 use lazy_static::lazy_static;
-use percent_encoding::{AsciiSet, CONTROLS};
+use percent_encoding::{percent_encode_byte, AsciiSet, CONTROLS};
 use regex::Regex;
-use reqwest::Client;
-use serde::Serialize;
+use reqwest::{Certificate, Client, Identity};
+use serde::{Deserialize, Serialize};
+use url::Url;
 
 use crate::{config::structs::Config, utils::random_line};
 
-use super::response::Response;
+use super::{raw, response::Response};
 
 lazy_static! {
     /// characters to encode in case --encode option provided
@@ -26,8 +27,56 @@ lazy_static! {
         .add(b'%');
 }
 
+/// characters to encode from --encode-chars. Control characters are always encoded.
+/// None in case there are non ascii characters (they are always encoded as well)
+pub fn ascii_set(chars: &str) -> Option<AsciiSet> {
+    if !chars.is_ascii() {
+        return None;
+    }
+
+    // 0x7f is within CONTROLS already, so it's just an owned copy of the set
+    Some(chars.bytes().fold(CONTROLS.add(0x7f), |set, x| set.add(x)))
+}
+
+/// how many times the query is percent-encoded (--encode)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Default)]
+pub enum EncodeMode {
+    #[default]
+    None,
+    Single,
+    /// the '%' from the first pass is encoded as well (= -> %3D -> %253D).
+    /// For WAFs that decode the request only once
+    Double,
+}
+
+impl EncodeMode {
+    pub fn encode(self, query: String, set: &AsciiSet) -> String {
+        match self {
+            EncodeMode::None => query,
+            EncodeMode::Single => percent_encode(&query, set),
+            EncodeMode::Double => percent_encode(&percent_encode(&query, set), set),
+        }
+    }
+}
+
+/// the same as utf8_percent_encode, but the set doesn't need to be static (--encode-chars)
+fn percent_encode(input: &str, set: &AsciiSet) -> String {
+    let mut encoded = String::with_capacity(input.len());
+
+    for &byte in input.as_bytes() {
+        // AsciiSet doesn't expose contains(), so adding a byte that's within the set doesn't change it
+        if !byte.is_ascii() || set.add(byte) == *set {
+            encoded.push_str(percent_encode_byte(byte));
+        } else {
+            encoded.push(byte as char);
+        }
+    }
+
+    encoded
+}
+
 /// enum mainly created for the correct json parsing
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum DataType {
     /// we need a different data type for json because some json values can be used without quotes (numbers, booleans, ..)
     /// and therefore this type should be treated differently
@@ -39,17 +88,69 @@ pub enum DataType {
     // it still can be bypassed with the correct --data-type argument
     ProbablyJson,
 
+    #[default]
     Urlencoded,
     Headers,
+
+    /// the format is fully defined by the template and the joiner (%k:%v and \n by default).
+    /// For csv, newline delimited key:value and other bodies
+    Delimited,
+
+    /// %s is replaced with the parameters as is: the injection point isn't added automatically
+    /// and Content-Type isn't sent. For formats the tool doesn't know about
+    Raw,
+
+    /// parameters are sent as elements (<%k>%v</%k>) within the root element. For SOAP and other xml apis
+    Xml,
+
+    /// every parameter is a form-data part. The boundary is random per request
+    Multipart,
 }
 
 /// where to insert parameters
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Copy)]
 pub enum InjectionPlace {
     Path,
     Body,
     Headers,
     HeaderValue,
+    /// after '#'. The fragment isn't sent to the server, so it's used only with --dry-run
+    Fragment,
+    /// within the single Cookie header (--cookie-discovery)
+    Cookie,
+}
+
+/// where the reflected value is within the response. Determines whether the reflection is exploitable (XSS)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReflectionContext {
+    /// within the response headers
+    Header,
+    /// between html tags
+    Html,
+    /// within a tag, outside of quotes: <input value=%s>
+    Tag,
+    /// <input value="%s">
+    DoubleQuotedAttribute,
+    /// <input value='%s'>
+    SingleQuotedAttribute,
+    /// within a <script> block
+    Script,
+    /// within a json response
+    Json,
+}
+
+impl ReflectionContext {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReflectionContext::Header => "header",
+            ReflectionContext::Html => "html",
+            ReflectionContext::Tag => "tag",
+            ReflectionContext::DoubleQuotedAttribute => "double-quoted attribute",
+            ReflectionContext::SingleQuotedAttribute => "single-quoted attribute",
+            ReflectionContext::Script => "script",
+            ReflectionContext::Json => "json",
+        }
+    }
 }
 
 impl Default for InjectionPlace {
@@ -58,6 +159,96 @@ impl Default for InjectionPlace {
     }
 }
 
+/// classes of transport errors. Only the ones from --retry-on are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    Timeout,
+    Connect,
+    /// the connection was closed while reading or decoding the body
+    Body,
+    /// certificate and handshake errors. They won't disappear on retry
+    Tls,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "timeout" => Some(ErrorClass::Timeout),
+            "connect" => Some(ErrorClass::Connect),
+            "body" => Some(ErrorClass::Body),
+            "tls" => Some(ErrorClass::Tls),
+            "other" => Some(ErrorClass::Other),
+            _ => None,
+        }
+    }
+
+    pub fn of(err: &(dyn Error + 'static)) -> Self {
+        // tls errors are wrapped within connect errors, so they are checked first
+        let mut source = Some(err);
+        while let Some(err) = source {
+            if err.is::<tokio_rustls::rustls::Error>() {
+                return ErrorClass::Tls;
+            }
+
+            // io::Error::source() skips the wrapped error itself
+            source = match err.downcast_ref::<io::Error>() {
+                Some(err) => err.get_ref().map(|x| x as &(dyn Error + 'static)),
+                None => err.source(),
+            };
+        }
+
+        if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+            if err.is_timeout() {
+                ErrorClass::Timeout
+            } else if err.is_connect() {
+                ErrorClass::Connect
+            } else if err.is_body() || err.is_decode() {
+                ErrorClass::Body
+            } else {
+                ErrorClass::Other
+            }
+        } else if let Some(err) = err.downcast_ref::<io::Error>() {
+            match err.kind() {
+                io::ErrorKind::TimedOut => ErrorClass::Timeout,
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted => ErrorClass::Connect,
+                io::ErrorKind::UnexpectedEof => ErrorClass::Body,
+                _ => ErrorClass::Other,
+            }
+        } else {
+            ErrorClass::Other
+        }
+    }
+}
+
+/// whether the response couldn't be parsed because of the missing or invalid status line.
+/// That's the way HTTP/0.9 responses fail when they aren't accepted (--disable-http09)
+pub fn is_invalid_version(err: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err.to_string() == "invalid HTTP version parsed" {
+            return true;
+        }
+        source = err.source();
+    }
+
+    false
+}
+
+/// HTTP/1.1, HTTP/2, ..
+pub fn version_to_str(version: http::Version) -> &'static str {
+    match version {
+        http::Version::HTTP_09 => "HTTP/0.9",
+        http::Version::HTTP_10 => "HTTP/1.0",
+        http::Version::HTTP_11 => "HTTP/1.1",
+        http::Version::HTTP_2 => "HTTP/2",
+        http::Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP/x",
+    }
+}
+
 pub trait Headers {
     fn contains_key(&self, key: &str) -> bool;
     fn get_index_case_insensitive(&self, key: &str) -> Option<usize>;
@@ -100,14 +291,132 @@ impl Headers for Vec<(String, String)> {
     }
 }
 
+/// parses \r, \n, \t, \0, \\, \xNN and \uNNNN escape sequences.
+/// Unknown or malformed sequences are left as is
+pub fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        let escaped = match chars.peek() {
+            Some('r') => Some('\r'),
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('0') => Some('\0'),
+            Some('\\') => Some('\\'),
+            Some(&kind @ ('x' | 'u')) => {
+                let len = if kind == 'x' { 2 } else { 4 };
+                let hex: String = chars.clone().skip(1).take(len).collect();
+
+                let parsed = if hex.len() == len {
+                    u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                } else {
+                    None
+                };
+
+                if parsed.is_some() {
+                    // skip x/u and all the digits except the last one (it's skipped below as any other escaped char)
+                    chars.nth(len - 1);
+                }
+                parsed
+            }
+            _ => None,
+        };
+
+        match escaped {
+            Some(val) => {
+                chars.next();
+                unescaped.push(val);
+            }
+            None => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// returns the absolute url of the <meta http-equiv="refresh"> or simple javascript (location = '..') redirect
+pub fn client_redirect(base: &str, text: &str) -> Option<String> {
+    lazy_static! {
+        static ref RE_META_REFRESH: Regex =
+            Regex::new(r#"(?i)<meta[^>]+http-equiv\s*=\s*["']?refresh[^>]*>"#).unwrap();
+        static ref RE_META_URL: Regex =
+            Regex::new(r#"(?i)content\s*=\s*["']?\s*\d*\s*;?\s*url\s*=\s*['"]?([^"'>\s]+)"#).unwrap();
+        static ref RE_JS_REDIRECT: Regex = Regex::new(
+            r#"location(?:\.href)?\s*=\s*["']([^"']+)["']|location\.(?:replace|assign)\(\s*["']([^"']+)["']"#
+        )
+        .unwrap();
+    }
+
+    let target = match RE_META_REFRESH.find(text) {
+        Some(tag) => RE_META_URL.captures(tag.as_str())?.get(1)?.as_str(),
+        None => {
+            let cap = RE_JS_REDIRECT.captures(text)?;
+            cap.get(1).or_else(|| cap.get(2))?.as_str()
+        }
+    };
+
+    let url = Url::parse(base).ok()?.join(target).ok()?;
+
+    // javascript:, data:, etc
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+
+    Some(url.to_string())
+}
+
+/// replaces {{file:path}} with the trimmed content of the file.
+/// The file is read every time, so it can be updated by another tool during the scan
+pub fn replace_file_templates(value: &str) -> Result<String, Box<dyn Error>> {
+    lazy_static! {
+        static ref RE_FILE_TEMPLATE: Regex = Regex::new(r"\{\{file:([^}]+)\}\}").unwrap();
+    }
+
+    if !value.contains("{{file:") {
+        return Ok(value.to_string());
+    }
+
+    let mut replaced = String::with_capacity(value.len());
+    let mut last_end = 0;
+
+    for cap in RE_FILE_TEMPLATE.captures_iter(value) {
+        let whole = cap.get(0).unwrap();
+        let path = &cap[1];
+
+        let content = match fs::read_to_string(path) {
+            Ok(val) => val,
+            Err(err) => Err(format!("Unable to read {} ({})", path, err))?,
+        };
+
+        replaced.push_str(&value[last_end..whole.start()]);
+        replaced.push_str(content.trim());
+        last_end = whole.end();
+    }
+
+    replaced.push_str(&value[last_end..]);
+
+    Ok(replaced)
+}
+
 /// writes request and response to a file
 /// return file location
-pub(super) fn save_request(
+pub fn save_request(
     config: &Config,
     response: &Response,
+    initial_response: &Response,
     param_key: &str,
 ) -> Result<String, Box<dyn Error>> {
-    let output = response.print_all();
+    let mut output = response.print_all();
+
+    if config.save_diffs {
+        output += &format!("\n\n{}", response.print_diff(initial_response)?);
+    }
 
     let filename = format!(
         "{}/{}-{}-{}-{}",
@@ -129,15 +438,90 @@ pub(super) fn save_request(
     Ok(filename)
 }
 
+/// loads the client certificate and the key for mutual TLS. None in case neither of them is provided
+pub fn client_identity(
+    cert_path: Option<&str>,
+    key_path: Option<&str>,
+) -> Result<Option<Identity>, Box<dyn Error>> {
+    let (cert_path, key_path) = match (cert_path, key_path) {
+        (None, None) => return Ok(None),
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (Some(_), None) => Err("--client-cert requires the private key (--client-key)")?,
+        (None, Some(_)) => Err("--client-key requires the certificate (--client-cert)")?,
+    };
+
+    let read =
+        |path: &str| fs::read(path).map_err(|err| format!("Unable to read {}: {}", path, err));
+
+    // reqwest expects both the certificate and the key within the same pem
+    let mut pem = read(cert_path)?;
+    pem.push(b'\n');
+    pem.extend(read(key_path)?);
+
+    match Identity::from_pem(&pem) {
+        Ok(val) => Ok(Some(val)),
+        Err(err) => Err(format!("Unable to load the client certificate: {}", err))?,
+    }
+}
+
+/// loads the certificates from the bundle of concatenated PEM certificates
+pub fn ca_certificates(path: &str) -> Result<Vec<Certificate>, Box<dyn Error>> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+
+    let bundle =
+        fs::read_to_string(path).map_err(|err| format!("Unable to read {}: {}", path, err))?;
+
+    let certificates = bundle
+        .split(BEGIN)
+        .skip(1)
+        .map(|x| {
+            Certificate::from_pem(format!("{}{}", BEGIN, x).as_bytes())
+                .map_err(|err| format!("Unable to parse the certificate from {}: {}", path, err))
+        })
+        .collect::<Result<Vec<Certificate>, String>>()?;
+
+    if certificates.is_empty() {
+        Err(format!("No certificates found within {}", path))?
+    }
+
+    Ok(certificates)
+}
+
 pub fn create_client(config: &Config, replay: bool) -> Result<Client, Box<dyn Error>> {
     let mut client = Client::builder()
-        .danger_accept_invalid_certs(true)
+        // invalid certificates are accepted unless the user wants to verify them against the own CA
+        .danger_accept_invalid_certs(config.ca_bundle.is_none())
         .timeout(Duration::from_secs(config.timeout as u64))
         .http1_title_case_headers()
         .cookie_store(true)
-        .http09_responses()
         .use_rustls_tls();
 
+    if config.http09_responses {
+        client = client.http09_responses();
+    }
+
+    if config.connection_close {
+        client = client.pool_max_idle_per_host(0);
+    }
+
+    // the same certificate checks as with danger_accept_invalid_certs, but with the custom ALPN protocols
+    if let Some(alpn) = &config.alpn {
+        client = client.use_preconfigured_tls(raw::tls_config(raw::alpn_protocols(alpn)));
+    }
+
+    if let Some(ca_bundle) = &config.ca_bundle {
+        for certificate in ca_certificates(ca_bundle)? {
+            client = client.add_root_certificate(certificate);
+        }
+    }
+
+    if let Some(identity) = client_identity(
+        config.client_cert_path.as_deref(),
+        config.client_key_path.as_deref(),
+    )? {
+        client = client.identity(identity);
+    }
+
     if config.disable_trustdns {
         client = client.no_trust_dns();
     }
@@ -147,10 +531,22 @@ pub fn create_client(config: &Config, replay: bool) -> Result<Client, Box<dyn Er
             Ok(val) => val,
             Err(err) => Err(format!("Unable to parse replay_proxy: {}", err))?,
         });
-    } else {
+    } else if !config.proxy.is_empty()
+        || !config.http_proxy.is_empty()
+        || !config.https_proxy.is_empty()
+    {
+        // scheme specific proxies are checked first
+        if !config.http_proxy.is_empty() {
+            client = client.proxy(reqwest::Proxy::http(&config.http_proxy)?);
+        }
+        if !config.https_proxy.is_empty() {
+            client = client.proxy(reqwest::Proxy::https(&config.https_proxy)?);
+        }
         if !config.proxy.is_empty() {
             client = client.proxy(reqwest::Proxy::all(&config.proxy)?);
         }
+    } else if let Some(proxy) = env_proxy().filter(|_| config.env_proxy) {
+        client = client.proxy(proxy);
     }
 
     if !config.follow_redirects {
@@ -168,20 +564,104 @@ pub fn create_client(config: &Config, replay: bool) -> Result<Client, Box<dyn Er
     Ok(client.build()?)
 }
 
+/// the proxy from HTTP_PROXY, HTTPS_PROXY or ALL_PROXY environment variables (lowercase ones as well)
+/// hosts from NO_PROXY (comma separated domains or *) are requested directly
+fn env_proxy() -> Option<reqwest::Proxy> {
+    let var = |name: &str| {
+        env::var(name)
+            .or_else(|_| env::var(name.to_lowercase()))
+            .ok()
+            .filter(|x| !x.trim().is_empty())
+    };
+
+    let (http, https, all) = (var("HTTP_PROXY"), var("HTTPS_PROXY"), var("ALL_PROXY"));
+
+    if http.is_none() && https.is_none() && all.is_none() {
+        return None;
+    }
+
+    let no_proxy: Vec<String> = var("NO_PROXY")
+        .unwrap_or_default()
+        .split(',')
+        .map(|x| x.trim().trim_start_matches('.').to_lowercase())
+        .filter(|x| !x.is_empty())
+        .collect();
+
+    Some(reqwest::Proxy::custom(move |url| {
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+
+        if no_proxy
+            .iter()
+            .any(|x| x == "*" || host == *x || host.ends_with(&format!(".{}", x)))
+        {
+            return None;
+        }
+
+        match url.scheme() {
+            "https" => https.clone().or_else(|| all.clone()),
+            _ => http.clone().or_else(|| all.clone()),
+        }
+    }))
+}
+
+/// content types that are treated as binary by default
+const BINARY_MIMES: &str = "(video|audio|font|image)/\
+    |\
+    /(zip|octet-stream|x-tar|vnd\\.rar|pdf|gzip|epub-zip|x-bzip|x-bzip2|x-freearc|x-7z-compressed)";
+
+/// decides which content types are binary.
+/// Built once from the defaults, --binary-mimes and --binary-application
+#[derive(Debug, Clone)]
+pub struct BinaryMimes {
+    regex: Regex,
+
+    /// every application/* type except the textual ones (json, xml, javascript, ..)
+    all_application: bool,
+}
+
+impl BinaryMimes {
+    /// extra - substrings of content types to add to the default ones (wasm, protobuf)
+    pub fn new(extra: &[String], all_application: bool) -> Result<Self, regex::Error> {
+        let regex = std::iter::once(BINARY_MIMES.to_string())
+            .chain(extra.iter().map(|x| regex::escape(x)))
+            .collect::<Vec<String>>()
+            .join("|");
+
+        Ok(Self {
+            regex: Regex::new(&regex)?,
+            all_application,
+        })
+    }
+
+    pub fn is_match(&self, content_type: &str) -> bool {
+        lazy_static! {
+            static ref RE_TEXT_APPLICATION: Regex =
+                Regex::new("json|xml|javascript|ecmascript|x-www-form-urlencoded").unwrap();
+        }
+
+        self.regex.is_match(content_type)
+            || (self.all_application
+                && content_type.starts_with("application/")
+                && !RE_TEXT_APPLICATION.is_match(content_type))
+    }
+}
+
+impl Default for BinaryMimes {
+    fn default() -> Self {
+        lazy_static! {
+            static ref DEFAULT: BinaryMimes = BinaryMimes::new(&[], false).unwrap();
+        }
+
+        DEFAULT.clone()
+    }
+}
+
 /// check whether the content is binary
 /// so we can ignore the body in comparing
 /// a few reasons for it:
 /// 1. the comparing of binary content takes a lot of time
 /// 2. page diff anyway will be checked by the content-length header
 /// because the content-length header usually static for binary files
-pub fn is_binary_content(content_type: Option<String>) -> bool {
-    lazy_static! {
-        static ref RE_BINARY_MIME: Regex = Regex::new(
-            "((video|audio|font|image)/\
-    |\
-    /(zip|octet-stream|x-tar|vnd\\.rar|pdf|gzip|epub-zip|x-bzip|x-bzip2|x-freearc|x-7z-compressed))"
-        ).unwrap();
-    }
-
-    content_type.is_some() && RE_BINARY_MIME.is_match(&content_type.unwrap())
+pub fn is_binary_content(content_type: Option<String>, binary_mimes: &BinaryMimes) -> bool {
+    content_type.is_some_and(|x| binary_mimes.is_match(&x.to_lowercase()))
 }
@@ -0,0 +1,195 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use rand::Rng;
+
+use super::request::Request;
+
+/// writes every request & response pair to a WARC 1.1 file
+#[derive(Debug)]
+pub struct WarcWriter {
+    file: Mutex<File>,
+}
+
+impl WarcWriter {
+    /// creates the file and writes the warcinfo record
+    pub fn new(filename: &str) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::create(filename)?;
+
+        let info = format!(
+            "software: x8/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+
+        file.write_all(&WarcWriter::record(
+            "warcinfo",
+            None,
+            &format_date(SystemTime::now()),
+            &record_id(),
+            None,
+            "application/warc-fields",
+            info.as_bytes(),
+        ))?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// writes request and response records.
+    /// The request's date is calculated from the response's date and the time it took to get the response
+    pub fn write(
+        &self,
+        request: &Request,
+        http_version: http::Version,
+        code: u16,
+        headers: &[(String, String)],
+        body: &[u8],
+        time: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let url = request.url();
+        let response_date = SystemTime::now();
+        let request_date = response_date.checked_sub(time).unwrap_or(response_date);
+        let request_id = record_id();
+
+        let mut request_block = format!(
+            "{} {} {:?}\r\n",
+            request.defaults.method, request.path, http_version
+        );
+        if !request
+            .headers
+            .iter()
+            .any(|(k, _)| k.to_lowercase() == "host")
+        {
+            request_block += &format!("Host: {}\r\n", request.defaults.host);
+        }
+        for (k, v) in request.headers.iter() {
+            request_block += &format!("{}: {}\r\n", k, v);
+        }
+        request_block += "\r\n";
+        request_block += &request.body;
+
+        let mut response_block = format!("{:?} {}\r\n", http_version, code).into_bytes();
+        for (k, v) in headers.iter() {
+            response_block.extend(format!("{}: {}\r\n", k, v).as_bytes());
+        }
+        response_block.extend(b"\r\n");
+        response_block.extend(body);
+
+        let mut records = WarcWriter::record(
+            "request",
+            Some(&url),
+            &format_date(request_date),
+            &request_id,
+            None,
+            "application/http;msgtype=request",
+            request_block.as_bytes(),
+        );
+        records.append(&mut WarcWriter::record(
+            "response",
+            Some(&url),
+            &format_date(response_date),
+            &record_id(),
+            Some(&request_id),
+            "application/http;msgtype=response",
+            &response_block,
+        ));
+
+        let mut file = self.file.lock();
+        file.write_all(&records)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    fn record(
+        warc_type: &str,
+        target_uri: Option<&str>,
+        date: &str,
+        id: &str,
+        concurrent_to: Option<&str>,
+        content_type: &str,
+        block: &[u8],
+    ) -> Vec<u8> {
+        let mut header = format!(
+            "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+            warc_type, id, date
+        );
+
+        if let Some(val) = target_uri {
+            header += &format!("WARC-Target-URI: {}\r\n", val);
+        }
+
+        if let Some(val) = concurrent_to {
+            header += &format!("WARC-Concurrent-To: {}\r\n", val);
+        }
+
+        header += &format!(
+            "Content-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            content_type,
+            block.len()
+        );
+
+        let mut record = header.into_bytes();
+        record.extend(block);
+        record.extend(b"\r\n\r\n");
+
+        record
+    }
+}
+
+/// random <urn:uuid:..> (v4)
+fn record_id() -> String {
+    let mut rng = rand::thread_rng();
+    let mut bytes: Vec<u8> = (0..16).map(|_| rng.gen::<u8>()).collect();
+
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|x| format!("{:02x}", x)).collect();
+
+    format!(
+        "<urn:uuid:{}-{}-{}-{}-{}>",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// formats the time as in WARC-Date (YYYY-MM-DDThh:mm:ssZ)
+pub(super) fn format_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0);
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // converts days since the epoch to the civil date
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
@@ -0,0 +1,84 @@
+use std::{collections::HashMap, error::Error, fs};
+
+use serde::{Deserialize, Serialize};
+
+use crate::network::request::RequestDefaults;
+
+use super::utils::{FoundParameter, Parameters};
+
+/// progress of the whole scan. Gets written to --checkpoint file after every checked chunk
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// where to save the checkpoint
+    #[serde(skip)]
+    pub filename: String,
+
+    /// <url (injection place), progress>
+    pub entries: HashMap<String, CheckpointEntry>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CheckpointEntry {
+    /// parameters that were already sent
+    pub tested: Vec<String>,
+
+    /// parameters that were found so far
+    pub found_params: Vec<FoundParameter>,
+}
+
+impl Checkpoint {
+    /// reads the checkpoint from the file or creates an empty one if there's nothing to resume from
+    pub fn new(filename: &str, resume: bool) -> Result<Self, Box<dyn Error>> {
+        let mut checkpoint = if resume && fs::metadata(filename).is_ok() {
+            serde_json::from_str(&fs::read_to_string(filename)?)?
+        } else {
+            Checkpoint::default()
+        };
+
+        checkpoint.filename = filename.to_string();
+
+        Ok(checkpoint)
+    }
+
+    /// progress is stored per url and injection place
+    fn key(request_defaults: &RequestDefaults) -> String {
+        format!(
+            "{} ({:?})",
+            request_defaults.url(),
+            request_defaults.injection_place
+        )
+    }
+
+    /// returns the already covered part of the scan
+    pub fn get(&self, request_defaults: &RequestDefaults) -> Option<&CheckpointEntry> {
+        self.entries.get(&Checkpoint::key(request_defaults))
+    }
+
+    /// marks parameters as tested, stores new findings and writes everything to the file
+    pub fn update(
+        &mut self,
+        request_defaults: &RequestDefaults,
+        tested: &[String],
+        found_params: &[FoundParameter],
+    ) -> Result<(), Box<dyn Error>> {
+        let entry = self
+            .entries
+            .entry(Checkpoint::key(request_defaults))
+            .or_default();
+
+        entry.tested.extend(tested.iter().cloned());
+
+        for param in found_params {
+            if !entry.found_params.contains_element(param) {
+                entry.found_params.push(param.clone());
+            }
+        }
+
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        fs::write(&self.filename, serde_json::to_string(&self)?)?;
+        Ok(())
+    }
+}
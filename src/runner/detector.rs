@@ -0,0 +1,205 @@
+use std::{error::Error, fmt::Debug, sync::Arc};
+
+use regex::Regex;
+
+use crate::network::{response::Response, utils::Headers};
+
+use super::utils::ReasonKind;
+
+/// the result of the detector's evaluation
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub reason_kind: ReasonKind,
+
+    /// short explanation of the difference, for example "length 120 -> 4096"
+    pub description: String,
+}
+
+/// decides whether the response with parameters differs from the baseline one in an interesting way.
+/// Detectors are checked for every request (including the ones with a lot of parameters),
+/// so the parameter is found via the same binary search as the built-in checks
+pub trait Detector: Debug + Send + Sync {
+    fn evaluate(&self, baseline: &Response, candidate: &Response) -> Option<Finding>;
+}
+
+/// the amount of reflections of random values differs from the expected one
+#[derive(Debug)]
+pub struct ReflectionDetector;
+
+impl Detector for ReflectionDetector {
+    fn evaluate(&self, baseline: &Response, candidate: &Response) -> Option<Finding> {
+        let request = candidate.request.as_ref()?;
+
+        let parameter = request
+            .prepared_parameters
+            .iter()
+            .filter(|(k, _)| !request.non_random_parameters.contains_key(k))
+            .find(|(_, v)| {
+                candidate.count(v).saturating_sub(baseline.count(v))
+                    != request.defaults.amount_of_reflections
+            })?;
+
+        Some(Finding {
+            reason_kind: ReasonKind::Reflected,
+            description: format!("{} reflections changed", parameter.0),
+        })
+    }
+}
+
+/// the status code differs. In case `codes` isn't empty, only the listed codes and transitions are reported
+#[derive(Debug, Default)]
+pub struct CodeDetector {
+    /// (baseline code, candidate code). The baseline code is None in case any code is accepted
+    pub codes: Vec<(Option<u16>, u16)>,
+}
+
+impl CodeDetector {
+    /// parses comma separated codes and transitions like 500,404->200
+    pub fn new(value: &str) -> Result<Self, Box<dyn Error>> {
+        let codes = value
+            .split(',')
+            .map(|x| match x.split_once("->") {
+                Some((from, to)) => Ok((Some(from.trim().parse()?), to.trim().parse()?)),
+                None => Ok((None, x.trim().parse()?)),
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+        Ok(Self { codes })
+    }
+}
+
+impl Detector for CodeDetector {
+    fn evaluate(&self, baseline: &Response, candidate: &Response) -> Option<Finding> {
+        if baseline.code == candidate.code {
+            return None;
+        }
+
+        if !self.codes.is_empty()
+            && !self.codes.iter().any(|(from, to)| {
+                *to == candidate.code && (from.is_none() || *from == Some(baseline.code))
+            })
+        {
+            return None;
+        }
+
+        Some(Finding {
+            reason_kind: ReasonKind::Code,
+            description: format!("code {} -> {}", baseline.code, candidate.code),
+        })
+    }
+}
+
+/// the body length differs by more than `threshold` bytes
+#[derive(Debug)]
+pub struct LengthDetector {
+    pub threshold: usize,
+}
+
+impl Detector for LengthDetector {
+    fn evaluate(&self, baseline: &Response, candidate: &Response) -> Option<Finding> {
+        if baseline.body_length.abs_diff(candidate.body_length) <= self.threshold {
+            return None;
+        }
+
+        Some(Finding {
+            reason_kind: ReasonKind::Custom,
+            description: format!(
+                "length {} -> {}",
+                baseline.body_length, candidate.body_length
+            ),
+        })
+    }
+}
+
+/// the response takes more than `threshold` millisecs longer
+#[derive(Debug)]
+pub struct TimeDetector {
+    pub threshold: u128,
+}
+
+impl Detector for TimeDetector {
+    fn evaluate(&self, baseline: &Response, candidate: &Response) -> Option<Finding> {
+        if candidate.time <= baseline.time + self.threshold {
+            return None;
+        }
+
+        Some(Finding {
+            reason_kind: ReasonKind::Custom,
+            description: format!("time {}ms -> {}ms", baseline.time, candidate.time),
+        })
+    }
+}
+
+/// the regex matches the response but not the baseline.
+/// --interesting uses it with its own reason kind
+#[derive(Debug)]
+pub struct RegexDetector {
+    pub regex: Regex,
+    pub reason_kind: ReasonKind,
+}
+
+impl Detector for RegexDetector {
+    fn evaluate(&self, baseline: &Response, candidate: &Response) -> Option<Finding> {
+        if !self.regex.is_match(&candidate.text) || self.regex.is_match(&baseline.text) {
+            return None;
+        }
+
+        Some(Finding {
+            reason_kind: self.reason_kind.clone(),
+            description: format!("matches {}", self.regex),
+        })
+    }
+}
+
+/// the parameter flips whether the body matches --match-regex (in any direction).
+/// The regex itself is stored within the request defaults, so it's compiled only once
+#[derive(Debug)]
+pub struct MatchDetector;
+
+impl Detector for MatchDetector {
+    fn evaluate(&self, baseline: &Response, candidate: &Response) -> Option<Finding> {
+        let matches = candidate.matches_custom();
+        if matches == baseline.matches_custom() {
+            return None;
+        }
+
+        Some(Finding {
+            reason_kind: ReasonKind::Custom,
+            description: if matches {
+                "starts matching --match-regex".to_string()
+            } else {
+                "stops matching --match-regex".to_string()
+            },
+        })
+    }
+}
+
+/// parses detectors like reflection, code, code:500,404->200, length:100, time:2000, regex:<pattern>
+pub fn parse_detector(value: &str) -> Result<Arc<dyn Detector>, Box<dyn Error>> {
+    let (name, argument) = match value.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (value, None),
+    };
+
+    let detector: Arc<dyn Detector> = match (name, argument) {
+        ("reflection", None) => Arc::new(ReflectionDetector),
+        ("code", None) => Arc::new(CodeDetector::default()),
+        ("code", Some(val)) => Arc::new(CodeDetector::new(val)?),
+        ("length", Some(val)) => Arc::new(LengthDetector {
+            threshold: val.parse()?,
+        }),
+        ("time", Some(val)) => Arc::new(TimeDetector {
+            threshold: val.parse()?,
+        }),
+        ("regex", Some(val)) => Arc::new(RegexDetector {
+            regex: Regex::new(val)?,
+            reason_kind: ReasonKind::Custom,
+        }),
+        _ => Err(format!(
+            "Unable to parse the detector {}. Supported: reflection, code[:<codes>], length:<bytes>, time:<ms>, regex:<pattern>",
+            value
+        ))?,
+    };
+
+    Ok(detector)
+}
@@ -4,17 +4,24 @@ use async_recursion::async_recursion;
 use futures::stream::StreamExt;
 use parking_lot::Mutex;
 
+use reqwest::Client;
+
 use crate::{
-    network::request::Request,
-    runner::utils::{FoundParameter, ReasonKind},
-    utils::progress_style_check_requests,
+    network::{
+        request::Request,
+        response::Response,
+        utils::{create_client, InjectionPlace},
+    },
+    runner::utils::{FoundParameter, Parameters, ReasonKind},
+    utils::{self, progress_style_check_requests, random_line},
 };
 
 use super::runner::Runner;
 
 /// impl logic for checking parameters
 impl<'a> Runner<'a> {
-    /// just splits params into two parts and runs check_parameters_recursion for every part
+    /// splits params into two parts and runs check_parameters_recursion for both parts concurrently.
+    /// The amount of in-flight requests is still limited by the semaphore from Runner::new
     async fn repeat(
         &self,
         shared_diffs: Arc<Mutex<&'a mut Vec<String>>>,
@@ -24,20 +31,59 @@ impl<'a> Runner<'a> {
     ) -> Result<(), Box<dyn Error>> {
         let second_params_part = params.split_off(params.len() / 2);
 
-        self.check_parameters_recursion(
-            Arc::clone(&shared_diffs),
-            Arc::clone(&shared_green_lines),
-            Arc::clone(&shared_found_params),
-            params,
-        )
-        .await?;
-        self.check_parameters_recursion(
-            shared_diffs,
-            shared_green_lines,
-            shared_found_params,
-            second_params_part,
-        )
-        .await
+        let (first, second) = futures::join!(
+            self.check_parameters_recursion(
+                Arc::clone(&shared_diffs),
+                Arc::clone(&shared_green_lines),
+                Arc::clone(&shared_found_params),
+                params,
+            ),
+            self.check_parameters_recursion(
+                shared_diffs,
+                shared_green_lines,
+                shared_found_params,
+                second_params_part,
+            )
+        );
+
+        first.and(second)
+    }
+
+    /// resends the parameters self.confirm times and checks whether the signal reproduces
+    /// at least in config.confirm_threshold part of the responses. Helps against flaky backends
+    async fn confirm<'b, F>(&'b self, params: &[String], check: F) -> bool
+    where
+        F: Fn(&mut Response<'b>) -> bool,
+    {
+        if self.confirm == 0 {
+            return true;
+        }
+
+        let mut reproduced = 0;
+
+        for _ in 0..self.confirm {
+            if let Ok(mut response) = Request::new(&self.request_defaults, params.to_vec())
+                .send()
+                .await
+            {
+                if check(&mut response) {
+                    reproduced += 1;
+                }
+            }
+        }
+
+        let is_confirmed = reproduced as f64 >= self.confirm as f64 * self.config.confirm_threshold;
+
+        if !is_confirmed {
+            log::debug!(
+                "{} wasn't confirmed ({}/{})",
+                params.join(", "),
+                reproduced,
+                self.confirm
+            );
+        }
+
+        is_confirmed
     }
 
     #[async_recursion(?Send)]
@@ -50,24 +96,192 @@ impl<'a> Runner<'a> {
     ) -> Result<(), Box<dyn Error>> {
         let request = Request::new(&self.request_defaults, params.clone());
         let mut response = match request.clone().wrapped_send().await {
-            Ok(val) => val,
-            Err(_) => match Request::new_random(&self.request_defaults, params.len())
+            Ok(val) => {
+                if self.config.timing_report != 0 {
+                    self.timings.add(&params, val.time);
+                }
+                val
+            }
+            Err(request_err) => match Request::new_random(&self.request_defaults, params.len())
                 .send()
                 .await
             {
                 //we don't return the actual response because it was a random request without original parameters
                 //instead we return an empty response from the original request
-                Ok(_) => request.empty_response(),
+                Ok(_) => request.empty_response(Some(request_err.to_string())),
                 //looks like either server or network is down
                 Err(err) => Err(format!("Unable to reach server ({})", err))?,
             },
         };
 
+        for parameter in response.location_reflected_parameters() {
+            // the guard must not live across the confirmation requests
+            let known = shared_found_params.lock().contains_name(&parameter);
+
+            if known
+                || !self
+                    .confirm(std::slice::from_ref(&parameter), |x| {
+                        x.location_reflected_parameters().contains(&parameter)
+                    })
+                    .await
+            {
+                continue;
+            }
+
+            let mut found_params = shared_found_params.lock();
+            if !found_params.iter().any(|x| x.name == parameter) {
+                found_params.push(
+                    FoundParameter::new(
+                        &parameter,
+                        &[],
+                        response.code,
+                        response.text.len(),
+                        ReasonKind::LocationReflected,
+                        response.index,
+                    )
+                    .with_response(self.config, &response),
+                );
+                drop(found_params);
+
+                response.write_and_save(
+                    self.id,
+                    self.config,
+                    &self.initial_response,
+                    ReasonKind::LocationReflected,
+                    &parameter,
+                    None,
+                    self.progress_bar,
+                )?;
+            }
+        }
+
+        if self.config.acknowledged {
+            for parameter in response.acknowledged_parameters(&self.initial_response) {
+                let known = shared_found_params.lock().contains_name(&parameter);
+
+                if known
+                    || !self
+                        .confirm(std::slice::from_ref(&parameter), |x| {
+                            x.acknowledged_parameters(&self.initial_response)
+                                .contains(&parameter)
+                        })
+                        .await
+                {
+                    continue;
+                }
+
+                shared_found_params.lock().push(
+                    FoundParameter::new(
+                        &parameter,
+                        &[],
+                        response.code,
+                        response.text.len(),
+                        ReasonKind::Acknowledged,
+                        response.index,
+                    )
+                    .with_response(self.config, &response),
+                );
+
+                response.write_and_save(
+                    self.id,
+                    self.config,
+                    &self.initial_response,
+                    ReasonKind::Acknowledged,
+                    &parameter,
+                    None,
+                    self.progress_bar,
+                )?;
+            }
+        }
+
+        for detector in self
+            .config
+            .detectors
+            .iter()
+            .chain(self.config.interesting.iter())
+        {
+            let finding = match detector.evaluate(&self.initial_response, &response) {
+                Some(val) => val,
+                None => continue,
+            };
+
+            // there's more than 1 parameter left - split the list and repeat
+            if params.len() != 1 {
+                return self
+                    .repeat(
+                        shared_diffs,
+                        shared_green_lines,
+                        shared_found_params,
+                        params.clone(),
+                    )
+                    .await;
+            }
+
+            if shared_found_params.lock().contains_name(&params[0]) {
+                return Ok(());
+            }
+
+            // the difference may be caused by the page itself rather than by the parameter
+            let check_response = Request::new_random(&self.request_defaults, params.len())
+                .send()
+                .await?;
+
+            if detector
+                .evaluate(&self.initial_response, &check_response)
+                .is_some()
+                || !self
+                    .confirm(&params, |x| {
+                        detector.evaluate(&self.initial_response, x).is_some()
+                    })
+                    .await
+            {
+                continue;
+            }
+
+            response.write_and_save(
+                self.id,
+                self.config,
+                &self.initial_response,
+                finding.reason_kind.clone(),
+                &params[0],
+                Some(&finding.description),
+                self.progress_bar,
+            )?;
+
+            shared_found_params.lock().push(
+                FoundParameter::new(
+                    &params[0],
+                    &[finding.description],
+                    response.code,
+                    response.text.len(),
+                    finding.reason_kind,
+                    response.index,
+                )
+                .with_response(self.config, &response),
+            );
+
+            return Ok(());
+        }
+
         if self.stable.reflections {
             response.fill_reflected_parameters(&self.initial_response);
 
             let (reflected_parameter, repeat) = response.proceed_reflected_parameters();
 
+            let reflected_parameter = match reflected_parameter {
+                Some(val)
+                    if !self
+                        .confirm(&[val.to_string()], |x| {
+                            x.fill_reflected_parameters(&self.initial_response);
+                            !x.reflected_parameters.is_empty()
+                        })
+                        .await =>
+                {
+                    None
+                }
+                val => val,
+            };
+
             if let Some(reflected_parameter) = reflected_parameter {
                 let mut found_params = shared_found_params.lock();
                 if !found_params.iter().any(|x| x.name == reflected_parameter) {
@@ -78,13 +292,37 @@ impl<'a> Runner<'a> {
                         kind = ReasonKind::NotReflected;
                     }
 
-                    found_params.push(FoundParameter::new(
+                    let mut found_param = FoundParameter::new(
                         reflected_parameter,
                         &vec![],
                         response.code,
                         response.text.len(),
                         kind.clone(),
-                    ));
+                        response.index,
+                    )
+                    .with_response(self.config, &response);
+
+                    if kind == ReasonKind::Reflected {
+                        if let Some((_, value)) = response
+                            .request
+                            .as_ref()
+                            .unwrap()
+                            .prepared_parameters
+                            .iter()
+                            .find(|(k, _)| k == reflected_parameter)
+                        {
+                            found_param.reflection_contexts = response.reflection_contexts(value);
+                        }
+                    }
+
+                    let contexts = found_param
+                        .reflection_contexts
+                        .iter()
+                        .map(|x| x.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ");
+
+                    found_params.push(found_param);
                     drop(found_params);
 
                     // remove found parameter from the list
@@ -101,7 +339,7 @@ impl<'a> Runner<'a> {
                         &self.initial_response,
                         kind,
                         reflected_parameter,
-                        None,
+                        (!contexts.is_empty()).then_some(contexts.as_str()),
                         self.progress_bar,
                     )?;
                 }
@@ -123,6 +361,33 @@ impl<'a> Runner<'a> {
             }
         }
 
+        // too many headers were sent (431 Request Header Fields Too Large)
+        // split them instead of treating the code change as a finding
+        if response.code == 431
+            && self.initial_response.code != 431
+            && self.request_defaults.injection_place == InjectionPlace::Headers
+        {
+            log::debug!("431 with {} headers, splitting", params.len());
+
+            if params.len() == 1 {
+                return Ok(());
+            }
+
+            return self
+                .repeat(
+                    shared_diffs,
+                    shared_green_lines,
+                    shared_found_params,
+                    params.clone(),
+                )
+                .await;
+        }
+
+        // the response is one of the page's legitimate variants (--baselines)
+        if self.matches_baseline(&response) {
+            return Ok(());
+        }
+
         if self.initial_response.code != response.code {
             // increases the specific response code counter
             // helps to notice whether the page's completely changed
@@ -161,6 +426,10 @@ impl<'a> Runner<'a> {
 
             // there's only 1 parameter left that's changing the page's code
             if params.len() == 1 {
+                if !self.confirm(&params, |x| x.code == response.code).await {
+                    return Ok(());
+                }
+
                 response.write_and_save(
                     self.id,
                     self.config,
@@ -172,16 +441,23 @@ impl<'a> Runner<'a> {
                 )?;
 
                 let mut found_params = shared_found_params.lock();
-                found_params.push(FoundParameter::new(
-                    &params[0],
-                    &vec![format!(
-                        "{} -> {}",
-                        &self.initial_response.code, response.code
-                    )],
-                    response.code,
-                    response.text.len(),
-                    ReasonKind::Code,
-                ));
+                found_params.push(
+                    FoundParameter::new(
+                        &params[0],
+                        &vec![match &response.error {
+                            Some(error) => format!(
+                                "{} -> {} ({})",
+                                &self.initial_response.code, response.code, error
+                            ),
+                            None => format!("{} -> {}", &self.initial_response.code, response.code),
+                        }],
+                        response.code,
+                        response.text.len(),
+                        ReasonKind::Code,
+                        response.index,
+                    )
+                    .with_response(self.config, &response),
+                );
             // there's more than 1 parameter left - split the list and repeat
             } else {
                 return self
@@ -226,6 +502,23 @@ impl<'a> Runner<'a> {
                 }
             }
 
+            // the diffs need to reproduce before the parameter is reported
+            if params.len() == 1 && self.config.confirm != 0 {
+                // cloned so the lock isn't held while the confirmation requests are sent
+                let known_diffs = shared_diffs.lock().clone();
+
+                if new_diffs.iter().any(|x| !known_diffs.contains(x))
+                    && !self
+                        .confirm(&params, |x| {
+                            x.compare(&self.initial_response, &known_diffs)
+                                .is_ok_and(|(_, diffs)| !diffs.is_empty())
+                        })
+                        .await
+                {
+                    return Ok(());
+                }
+            }
+
             let diffs = shared_diffs.lock();
 
             // check whether the page still(after making a random request and storing it's diffs) has an unique diffs
@@ -253,13 +546,17 @@ impl<'a> Runner<'a> {
                             self.progress_bar,
                         )?;
 
-                        found_params.push(FoundParameter::new(
-                            &params[0],
-                            &new_diffs,
-                            response.code,
-                            response.text.len(),
-                            ReasonKind::Text,
-                        ));
+                        found_params.push(
+                            FoundParameter::new(
+                                &params[0],
+                                &new_diffs,
+                                response.code,
+                                response.text.len(),
+                                ReasonKind::Text,
+                                response.index,
+                            )
+                            .with_response(self.config, &response),
+                        );
                         break;
                     // we don't know what parameter caused the difference in response yet
                     // so we are repeating
@@ -312,13 +609,35 @@ impl<'a> Runner<'a> {
             async move {
                 self.progress_bar.inc(1);
 
-                self.check_parameters_recursion(
-                    shared_diffs,
-                    shared_green_lines,
-                    shared_found_params,
-                    chunk.to_vec(),
-                )
-                .await
+                let result = self
+                    .check_parameters_recursion(
+                        shared_diffs,
+                        shared_green_lines,
+                        Arc::clone(&shared_found_params),
+                        chunk.to_vec(),
+                    )
+                    .await;
+
+                // the chunk is fully checked only in case there were no errors
+                if let (Some(checkpoint), Ok(())) = (&self.config.checkpoint, &result) {
+                    let found_params = shared_found_params.lock().to_vec();
+
+                    if let Err(err) =
+                        checkpoint
+                            .lock()
+                            .update(&self.request_defaults, chunk, &found_params)
+                    {
+                        utils::info(
+                            self.config,
+                            self.id,
+                            self.progress_bar,
+                            "~",
+                            format!("Unable to save the checkpoint: {}", err),
+                        );
+                    }
+                }
+
+                result
             }
         }))
         .buffer_unordered(self.config.concurrency)
@@ -327,4 +646,194 @@ impl<'a> Runner<'a> {
 
         Ok((diffs, found_params))
     }
+
+    /// sends parameters over HTTP/1.1 and HTTP/2 and finds the ones that change the page differently depending on the protocol.
+    /// The differences between the protocols that appear with random parameters are ignored
+    pub async fn check_protocol_differences(
+        &self,
+        params: &[String],
+    ) -> Result<Vec<FoundParameter>, Box<dyn Error>> {
+        let mut config = self.config.clone();
+        config.http_version = Some(http::Version::HTTP_11);
+        let http1_client = create_client(&config, false)?;
+        config.http_version = Some(http::Version::HTTP_2);
+        let http2_client = create_client(&config, false)?;
+
+        let (http1_response, http2_response) = self
+            .send_over_both_protocols(
+                &http1_client,
+                &http2_client,
+                Request::new_random(&self.request_defaults, self.max),
+            )
+            .await?;
+
+        if http2_response.http_version != Some(http::Version::HTTP_2) {
+            Err("HTTP/2 isn't supported")?
+        }
+
+        let (is_code_diff, known_diffs) = http2_response.compare(&http1_response, &[])?;
+        if is_code_diff {
+            Err(format!(
+                "the code differs even without parameters: {} -> {}",
+                http1_response.code, http2_response.code
+            ))?
+        }
+
+        let mut found_params = Vec::new();
+        let mut chunks: Vec<Vec<String>> = params
+            .chunks(cmp::max(self.max, 1))
+            .map(|x| x.to_vec())
+            .collect();
+
+        while let Some(mut chunk) = chunks.pop() {
+            let (http1_response, http2_response) = self
+                .send_over_both_protocols(
+                    &http1_client,
+                    &http2_client,
+                    Request::new(&self.request_defaults, chunk.clone()),
+                )
+                .await?;
+
+            let (is_code_diff, diffs) = http2_response.compare(&http1_response, &known_diffs)?;
+
+            if !is_code_diff && diffs.is_empty() {
+                continue;
+            }
+
+            if chunk.len() > 1 {
+                let second_part = chunk.split_off(chunk.len() / 2);
+                chunks.push(chunk);
+                chunks.push(second_part);
+                continue;
+            }
+
+            let description = if is_code_diff {
+                format!(
+                    "HTTP/1.1 code {} -> HTTP/2 code {}",
+                    http1_response.code, http2_response.code
+                )
+            } else {
+                format!("HTTP/1.1 -> HTTP/2 {}", diffs.join("|"))
+            };
+
+            http2_response.write_and_save(
+                self.id,
+                self.config,
+                &http1_response,
+                ReasonKind::Protocol,
+                &chunk[0],
+                Some(&description),
+                self.progress_bar,
+            )?;
+
+            found_params.push(
+                FoundParameter::new(
+                    &chunk[0],
+                    &[description],
+                    http2_response.code,
+                    http2_response.text.len(),
+                    ReasonKind::Protocol,
+                    http2_response.index,
+                )
+                .with_response(self.config, &http2_response),
+            );
+        }
+
+        Ok(found_params)
+    }
+
+    /// resends the found parameters without the Cookie and Authorization headers
+    /// and returns the ones that don't change the unauthenticated page (auth-gated parameters)
+    pub async fn check_auth_differences(
+        &self,
+        found_params: &[FoundParameter],
+    ) -> Result<Vec<FoundParameter>, Box<dyn Error>> {
+        let mut request_defaults = self.request_defaults.clone();
+
+        // headers with the injection point are kept
+        let amount_of_headers = request_defaults.custom_headers.len();
+        request_defaults.custom_headers.retain(|(k, v)| {
+            !(k.eq_ignore_ascii_case("cookie") || k.eq_ignore_ascii_case("authorization"))
+                || v.contains("%s")
+        });
+        if request_defaults.custom_headers.len() == amount_of_headers {
+            Err("there are no Cookie or Authorization headers")?
+        }
+
+        request_defaults.digest_auth = None;
+        request_defaults.token_refresher = None;
+        request_defaults.signer = None;
+        // a separate client, so cookies set during the authenticated scan aren't sent
+        request_defaults.client = create_client(self.config, false)?;
+
+        let mut temp_request_defaults = request_defaults.clone();
+        temp_request_defaults
+            .parameters
+            .push((random_line(10), random_line(10)));
+
+        let initial_response = Request::new(&temp_request_defaults, vec![]).send().await?;
+        request_defaults.amount_of_reflections =
+            initial_response.count(&temp_request_defaults.parameters.last().unwrap().1);
+
+        // the parts of the unauthenticated page that change on their own
+        let (_, known_diffs) = Request::new(&temp_request_defaults, vec![])
+            .send()
+            .await?
+            .compare(&initial_response, &[])?;
+
+        let mut auth_gated = Vec::new();
+
+        for param in found_params {
+            if matches!(
+                param.reason_kind,
+                ReasonKind::Protocol | ReasonKind::AuthGated
+            ) {
+                continue;
+            }
+
+            let (name, value) = param.get();
+            let mut response = Request::new(&request_defaults, vec![format!("{}={}", name, value)])
+                .send()
+                .await?;
+
+            let (is_code_diff, diffs) = response.compare(&initial_response, &known_diffs)?;
+            response.fill_reflected_parameters(&initial_response);
+
+            if is_code_diff || !diffs.is_empty() || !response.reflected_parameters.is_empty() {
+                continue;
+            }
+
+            response.write_and_save(
+                self.id,
+                self.config,
+                &initial_response,
+                ReasonKind::AuthGated,
+                &param.name,
+                None,
+                self.progress_bar,
+            )?;
+
+            auth_gated.push(FoundParameter {
+                reason_kind: ReasonKind::AuthGated,
+                ..param.clone()
+            });
+        }
+
+        Ok(auth_gated)
+    }
+
+    /// sends exactly the same request (with the same random values) via both clients
+    async fn send_over_both_protocols<'b>(
+        &self,
+        http1_client: &Client,
+        http2_client: &Client,
+        mut request: Request<'b>,
+    ) -> Result<(Response<'b>, Response<'b>), Box<dyn Error>> {
+        request.prepare()?;
+
+        Ok((
+            request.clone().send_by(http1_client).await?,
+            request.send_by(http2_client).await?,
+        ))
+    }
 }
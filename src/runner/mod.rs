@@ -1,4 +1,7 @@
+pub mod checkpoint;
+pub mod detector;
 pub mod logic;
+pub mod manifest;
 pub mod output;
 pub mod runner;
 pub mod utils;
@@ -1,12 +1,14 @@
 use colored::Colorize;
 use serde::Serialize;
+use serde_json::{json, Map, Value};
+use url::Url;
 
 use crate::{
-    config::structs::Config,
+    config::{error::QueryError, structs::Config},
     network::{
         request::{Request, RequestDefaults},
         response::Response,
-        utils::InjectionPlace,
+        utils::{DataType, InjectionPlace},
     },
 };
 
@@ -30,6 +32,14 @@ pub struct RunnerOutput {
 
     pub injection_place: InjectionPlace,
 
+    /// whether the body is json. Used for the openapi output
+    #[serde(skip_serializing)]
+    pub is_json: bool,
+
+    /// the format parameters are sent in. Used for the markdown output
+    #[serde(skip_serializing)]
+    pub data_type: String,
+
     /// prepared query with found parameters
     #[serde(skip_serializing)]
     pub query: String,
@@ -52,10 +62,13 @@ impl RunnerOutput {
         Self {
             method: request_defaults.method.clone(),
             //remove injection point in case the injection point within url
-            url: if request_defaults.injection_place == InjectionPlace::Path {
+            url: if request_defaults.injection_place == InjectionPlace::Path
+                || request_defaults.injection_place == InjectionPlace::Fragment
+            {
                 request_defaults
                     .url_without_default_port()
                     .replace("?%s", "")
+                    .replace("#%s", "")
                     .replace("&%s", "")
             } else {
                 request_defaults.url_without_default_port()
@@ -64,13 +77,19 @@ impl RunnerOutput {
             size: initial_response.text.len(),
             found_params,
             injection_place: request_defaults.injection_place,
+            is_json: request_defaults.data_type == DataType::Json,
+            data_type: request_defaults.data_type_name().to_string(),
             query: String::new(),
             request: String::new(),
         }
     }
 
     /// fills self.request and self.query if they're needed for output
-    pub fn prepare(&mut self, config: &Config, request_defaults: &RequestDefaults) {
+    pub fn prepare(
+        &mut self,
+        config: &Config,
+        request_defaults: &RequestDefaults,
+    ) -> Result<(), QueryError> {
         if config.output_format == "url" || config.output_format == "request" {
             let mut request = Request::new(
                 request_defaults,
@@ -91,14 +110,16 @@ impl RunnerOutput {
                     .collect(),
             );
 
-            request.prepare();
+            request.prepare()?;
 
             if config.output_format == "url" {
-                self.query = request.make_query();
+                self.query = request.make_query()?;
             } else {
-                self.request = request.print();
+                self.request = request.print()?;
             }
         }
+
+        Ok(())
     }
 
     /// parses the runner output struct to one specified in config format
@@ -106,8 +127,10 @@ impl RunnerOutput {
         match config.output_format.as_str() {
             "url" => {
                 //make line an url with injection point
+                // the injection point is kept within the value of a parameter (id=%s)
                 let line = if !self.found_params.is_empty()
                     && self.injection_place == InjectionPlace::Path
+                    && !self.url.contains("%s")
                 {
                     if !self.url.contains('?') {
                         self.url.clone() + "?%s"
@@ -144,6 +167,10 @@ impl ParseOutputs for Vec<RunnerOutput> {
         // print an array of json objects instead of just new line separeted new objects
         if config.output_format.as_str() == "json" {
             serde_json::to_string(&self).unwrap()
+        } else if config.output_format.as_str() == "openapi" {
+            serde_json::to_string_pretty(&openapi(self)).unwrap()
+        } else if config.output_format.as_str() == "markdown" {
+            markdown(self)
         // otherwise calls .parse on every RunnerOutput
         } else {
             self.iter()
@@ -153,3 +180,220 @@ impl ParseOutputs for Vec<RunnerOutput> {
         }
     }
 }
+
+/// merges parameters found within the same url via different injection places (e.g. GET query and POST body).
+/// The parameter is kept only within the first output and remembers all the places it was found within
+pub fn merge_findings(mut outputs: Vec<RunnerOutput>) -> Vec<RunnerOutput> {
+    for i in 0..outputs.len() {
+        for j in i + 1..outputs.len() {
+            if outputs[i].url != outputs[j].url
+                || outputs[i].injection_place == outputs[j].injection_place
+            {
+                continue;
+            }
+
+            let (first, second) = outputs.split_at_mut(j);
+            let (first, second) = (&mut first[i], &mut second[0]);
+            let (first_place, second_place) = (first.injection_place, second.injection_place);
+
+            second.found_params.retain(|found_param| {
+                match first
+                    .found_params
+                    .iter_mut()
+                    .find(|x| x.name == found_param.name)
+                {
+                    Some(param) => {
+                        if param.injection_places.is_empty() {
+                            param.injection_places.push(first_place);
+                        }
+                        if !param.injection_places.contains(&second_place) {
+                            param.injection_places.push(second_place);
+                        }
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+    }
+
+    outputs
+}
+
+/// builds a minimal OpenAPI 3.0 document with the found parameters.
+/// Path -> query, Headers -> header, HeaderValue -> cookie (header values are mostly used with --cookies),
+/// Body -> requestBody properties. Fragment parameters aren't sent to the server, so they are skipped
+pub fn openapi(outputs: &[RunnerOutput]) -> Value {
+    let mut servers: Vec<String> = Vec::new();
+    let mut paths = Map::new();
+
+    for output in outputs.iter().filter(|x| !x.found_params.is_empty()) {
+        let url = match Url::parse(&output.url) {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+
+        let server = url[..url::Position::BeforePath].to_string();
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+
+        let operation = paths
+            .entry(url.path().to_string())
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .unwrap()
+            .entry(output.method.to_lowercase())
+            .or_insert_with(|| json!({"responses": {"default": {"description": ""}}}))
+            .as_object_mut()
+            .unwrap();
+
+        for found_param in &output.found_params {
+            let places = if found_param.injection_places.is_empty() {
+                vec![output.injection_place]
+            } else {
+                found_param.injection_places.clone()
+            };
+
+            let mut schema = json!({"type": "string"});
+            if let Some(value) = &found_param.value {
+                schema["example"] = json!(value);
+            }
+
+            for place in places {
+                let location = match place {
+                    InjectionPlace::Path => "query",
+                    InjectionPlace::Headers => "header",
+                    InjectionPlace::HeaderValue | InjectionPlace::Cookie => "cookie",
+                    InjectionPlace::Fragment => continue,
+                    InjectionPlace::Body => {
+                        let content_type = if output.is_json {
+                            "application/json"
+                        } else {
+                            "application/x-www-form-urlencoded"
+                        };
+
+                        let request_body = operation
+                            .entry("requestBody")
+                            .or_insert_with(|| json!({"content": {}}));
+
+                        let media_type = request_body["content"]
+                            .as_object_mut()
+                            .unwrap()
+                            .entry(content_type)
+                            .or_insert_with(
+                                || json!({"schema": {"type": "object", "properties": {}}}),
+                            );
+
+                        media_type["schema"]["properties"][&found_param.name] = schema.clone();
+                        continue;
+                    }
+                };
+
+                let parameters = operation
+                    .entry("parameters")
+                    .or_insert_with(|| json!([]))
+                    .as_array_mut()
+                    .unwrap();
+
+                if !parameters
+                    .iter()
+                    .any(|x| x["name"] == found_param.name.as_str() && x["in"] == location)
+                {
+                    parameters.push(json!({
+                        "name": found_param.name,
+                        "in": location,
+                        "schema": schema,
+                    }));
+                }
+            }
+        }
+    }
+
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": "Parameters found by x8",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": servers.iter().map(|x| json!({"url": x})).collect::<Vec<Value>>(),
+        "paths": paths,
+    })
+}
+
+/// a report for sharing: the scan parameters and a table of the found parameters per url,
+/// followed by the collapsible requests and responses the parameters were found with
+pub fn markdown(outputs: &[RunnerOutput]) -> String {
+    // | and new lines would break the table
+    let escape = |x: &str| x.replace('|', "\\|").replace('\n', " ");
+
+    let mut report = String::from("# x8 report\n");
+
+    for output in outputs {
+        report += &format!(
+            "\n## {} {}\n\n- Method: `{}`\n- Injection place: `{:?}`\n- Data type: `{}`\n- Initial response: `{}`, {} bytes\n",
+            output.method,
+            output.url,
+            output.method,
+            output.injection_place,
+            output.data_type,
+            output.status,
+            output.size
+        );
+
+        if output.found_params.is_empty() {
+            report += "\nNo parameters found.\n";
+            continue;
+        }
+
+        report += "\n| Parameter | Reason | Reflections | Status | Length | Details |\n";
+        report += "|---|---|---|---|---|---|\n";
+
+        for found_param in &output.found_params {
+            let name = match &found_param.value {
+                Some(value) => format!("{}={}", found_param.name, value),
+                None => found_param.name.clone(),
+            };
+
+            let status = if found_param.status != output.status {
+                format!("{} -> {}", output.status, found_param.status)
+            } else {
+                found_param.status.to_string()
+            };
+
+            let length = if found_param.size != output.size {
+                format!(
+                    "{} -> {} ({:+})",
+                    output.size,
+                    found_param.size,
+                    found_param.size as i64 - output.size as i64
+                )
+            } else {
+                found_param.size.to_string()
+            };
+
+            report += &format!(
+                "| `{}` | {:?} | {} | {} | {} | {} |\n",
+                escape(&name),
+                found_param.reason_kind,
+                found_param.reflections,
+                status,
+                length,
+                escape(&found_param.diffs)
+            );
+        }
+
+        for found_param in &output.found_params {
+            if let Some(exchange) = &found_param.exchange {
+                report += &format!(
+                    "\n<details>\n<summary>{} (request #{})</summary>\n\n```http\n{}\n```\n\n</details>\n",
+                    found_param.name,
+                    found_param.request_index,
+                    exchange.trim_end()
+                );
+            }
+        }
+    }
+
+    report
+}
@@ -1,17 +1,21 @@
 use std::{
+    cmp,
     error::Error,
     io::{self, Write},
+    sync::Arc,
 };
 
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
+use tokio::sync::Semaphore;
 
 use crate::{
     config::{structs::Config, utils::file_writer},
     network::{
-        request::{Request, RequestDefaults},
+        block::similarity,
+        request::{Request, RequestDefaults, BLOCKED_ERROR},
         response::Response,
-        utils::{create_client, InjectionPlace},
+        utils::{create_client, Headers, InjectionPlace},
     },
     utils::{self, color_id, is_id_important, progress_style_learn_requests, random_line},
     DEFAULT_PROGRESS_URL_MAX_LEN, MAX_PAGE_SIZE,
@@ -19,9 +23,18 @@ use crate::{
 
 use super::{
     output::RunnerOutput,
-    utils::{fold_url, replay, verify, FoundParameter, Parameters, Stable},
+    utils::{
+        fold_url, replay, save_minimized, verify, Calibration, FoundParameter, Parameters, Stable,
+        Timings,
+    },
 };
 
+/// the min similarity of the warm up responses to the initial one (--warm-up)
+const WARM_UP_SIMILARITY: f64 = 0.9;
+
+/// the amount of confirmation requests in case the baseline is unstable (--warm-up-confirm)
+const WARM_UP_CONFIRM: usize = 3;
+
 pub struct Runner<'a> {
     /// unique id of the runner to distinguish output between different urls
     pub id: usize,
@@ -40,9 +53,23 @@ pub struct Runner<'a> {
     /// whether body or/and reflections are stable
     pub stable: Stable,
 
+    /// how many times to resend a found parameter to confirm it.
+    /// Taken from the config, but can be increased in case the baseline is unstable (--warm-up-confirm)
+    pub confirm: usize,
+
+    /// responses to random parameters. Empty unless --calibrate is provided
+    pub calibration: Calibration,
+
+    /// response times per parameter. Empty unless --timing-report is provided
+    pub timings: Timings,
+
     /// initial response to compare with
     pub initial_response: Response<'a>,
 
+    /// codes and bodies of the page's variants that differ from the initial response.
+    /// Empty unless --baselines is provided
+    pub baselines: Vec<(u16, String)>,
+
     /// page's diffs for the current url|method pair
     pub diffs: Vec<String>,
 
@@ -72,12 +99,17 @@ impl<'a> Runner<'a> {
         let initial_response = Request::new(&temp_request_defaults, vec![]).send().await?;
 
         // add possible parameters to the list of parameters in case the injection place is not headers
-        let possible_params = if request_defaults.injection_place != InjectionPlace::Headers {
+        let mut possible_params = if request_defaults.injection_place != InjectionPlace::Headers {
             initial_response.get_possible_parameters()
         } else {
             Vec::new()
         };
 
+        // the app may read the same parameters from both the query and the body
+        if config.query_candidates && request_defaults.injection_place == InjectionPlace::Body {
+            possible_params.splice(0..0, request_defaults.query_parameters());
+        }
+
         // find how many times was the random parameter reflected
         request_defaults.amount_of_reflections =
             initial_response.count(&temp_request_defaults.parameters.first().unwrap().1);
@@ -90,18 +122,38 @@ impl<'a> Runner<'a> {
             code: initial_response.code,
             headers: initial_response.headers,
             text: initial_response.text,
+            beautified_text: initial_response.beautified_text,
             reflected_parameters: initial_response.reflected_parameters,
             request: None,
             http_version: initial_response.http_version,
+            tls_info: initial_response.tls_info,
+            content_length: initial_response.content_length,
+            body_length: initial_response.body_length,
+            raw_request: initial_response.raw_request,
+            index: initial_response.index,
+            headers_truncated: initial_response.headers_truncated,
+            error: None,
         };
 
+        // the halves of the batches are checked concurrently (see repeat()),
+        // so the amount of in-flight requests is limited separately
+        let mut request_defaults = request_defaults.clone();
+        if request_defaults.semaphore.is_none() {
+            request_defaults.semaphore =
+                Some(Arc::new(Semaphore::new(cmp::max(config.concurrency, 1))));
+        }
+
         Ok(Runner {
             config,
-            request_defaults: request_defaults.clone(),
+            request_defaults,
             possible_params,
             max: 0, //to be filled later, in stability-checker()
             stable: Default::default(),
+            confirm: config.confirm,
+            calibration: Default::default(),
+            timings: Default::default(),
             initial_response,
+            baselines: Vec::new(),
             diffs: Vec::new(),
             progress_bar,
             id,
@@ -112,6 +164,16 @@ impl<'a> Runner<'a> {
     pub async fn run(mut self, params: &mut Vec<String>) -> Result<RunnerOutput, Box<dyn Error>> {
         self.write_banner_url();
 
+        if self.initial_response.headers_truncated {
+            utils::info(
+                self.config,
+                self.id,
+                self.progress_bar,
+                "~",
+                "Too many response headers, the rest are ignored (see --max-response-headers)",
+            );
+        }
+
         // makes a few request to check page's behavior
         self.stability_checker().await?;
 
@@ -160,9 +222,64 @@ impl<'a> Runner<'a> {
 
         self.check_non_random_parameters(&mut found_params).await?;
 
+        if self.config.timing_report != 0 {
+            self.write_timing_report();
+        }
+
+        // errors within separate chunks are skipped, so the block is checked explicitly
+        if let Some(block_detector) = &self.config.block_detector {
+            if block_detector.is_blocked() {
+                Err(BLOCKED_ERROR)?
+            }
+        }
+
+        if self.config.protocol_diff && !params.is_empty() {
+            match self.check_protocol_differences(params).await {
+                Ok(mut val) => found_params.append(&mut val),
+                Err(err) => utils::info(
+                    self.config,
+                    self.id,
+                    self.progress_bar,
+                    "~",
+                    format!("Unable to compare HTTP/1.1 and HTTP/2: {}", err),
+                ),
+            }
+        }
+
         // remove duplicates
         let mut found_params = found_params.process(self.request_defaults.injection_place);
 
+        // remove findings that look the same as responses to random parameters
+        let amount_of_reflections = self.request_defaults.amount_of_reflections;
+        let amount_of_found_params = found_params.len();
+        found_params.retain(|x| !self.calibration.matches(x, amount_of_reflections));
+
+        if found_params.len() != amount_of_found_params {
+            utils::info(
+                self.config,
+                self.id,
+                self.progress_bar,
+                "~",
+                format!(
+                    "{} parameters were suppressed as they match responses to random parameters",
+                    amount_of_found_params - found_params.len()
+                ),
+            );
+        }
+
+        if self.config.auth_diff && !found_params.is_empty() {
+            match self.check_auth_differences(&found_params).await {
+                Ok(mut val) => found_params.append(&mut val),
+                Err(err) => utils::info(
+                    self.config,
+                    self.id,
+                    self.progress_bar,
+                    "~",
+                    format!("Unable to check parameters without authentication: {}", err),
+                ),
+            }
+        }
+
         // verify found parameters
         if self.config.verify {
             found_params = if let Ok(filtered_params) = verify(
@@ -219,6 +336,39 @@ impl<'a> Runner<'a> {
             }
         }
 
+        // save the minimal requests that reproduce the found parameters
+        if !self.config.save_responses.is_empty() && !found_params.is_empty() {
+            match save_minimized(
+                self.config,
+                &self.initial_response,
+                &self.request_defaults,
+                &found_params,
+                &self.diffs,
+                &self.stable,
+            )
+            .await
+            {
+                Ok(filenames) => {
+                    for filename in filenames {
+                        utils::info(
+                            self.config,
+                            self.id,
+                            self.progress_bar,
+                            "info",
+                            format!("minimized request saved to {}", filename),
+                        );
+                    }
+                }
+                Err(_) => utils::info(
+                    self.config,
+                    self.id,
+                    self.progress_bar,
+                    "~",
+                    "was unable to save minimized requests",
+                ),
+            }
+        }
+
         Ok(RunnerOutput::new(
             &self.request_defaults,
             &self.initial_response,
@@ -226,6 +376,31 @@ impl<'a> Runner<'a> {
         ))
     }
 
+    /// prints parameters with the highest median response time
+    fn write_timing_report(&self) {
+        let slowest = self
+            .timings
+            .slowest(self.initial_response.time, self.config.timing_report);
+
+        if slowest.is_empty() {
+            return;
+        }
+
+        let mut report = format!(
+            "The slowest parameters (baseline {}ms):",
+            self.initial_response.time
+        );
+        for (param, median, diff) in slowest {
+            report += &format!("\n{}: {}ms (+{}ms)", param, median, diff);
+        }
+
+        file_writer(
+            self.config,
+            &(utils::info_return(self.config, self.id, "timing", &report) + "\n"),
+        );
+        utils::info(self.config, self.id, self.progress_bar, "timing", report);
+    }
+
     /// check parameters with non random values
     async fn check_non_random_parameters(
         &self,
@@ -268,14 +443,22 @@ impl<'a> Runner<'a> {
     /// makes several requests in order to learn how the page behaves
     /// tries to increase the max amount of parameters per request in case the default value not changed
     async fn stability_checker(&mut self) -> Result<(), Box<dyn Error>> {
+        self.warm_up().await?;
+
+        self.collect_baselines().await?;
+
         // guess or get from the user the amount of parameters to send per request
         let default_max = match self.config.max {
             Some(var) => var as isize,
+            // the value of a parameter holds a single word (id=%s)
+            None if self.request_defaults.value_injection => 1,
             None => match self.request_defaults.injection_place {
                 InjectionPlace::Body => -512,
                 InjectionPlace::Path => self.try_to_guess_the_right_max_for_query().await?,
-                InjectionPlace::Headers => -64,
-                InjectionPlace::HeaderValue => -64,
+                InjectionPlace::Headers | InjectionPlace::HeaderValue | InjectionPlace::Cookie => {
+                    self.try_to_guess_the_right_max_for_headers().await?
+                }
+                InjectionPlace::Fragment => -128,
             },
         };
 
@@ -293,6 +476,146 @@ impl<'a> Runner<'a> {
             self.try_to_increase_max().await?;
         }
 
+        self.calibrate().await?;
+
+        Ok(())
+    }
+
+    /// sends requests that are the same as the initial one and checks whether the responses are the same as well.
+    /// Warns in case they aren't because the results may be unreliable
+    async fn warm_up(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut reasons = Vec::new();
+
+        // the body of the first response without the reflected random value
+        let mut first_text: Option<String> = None;
+
+        for _ in 0..self.config.warm_up {
+            let response = Request::new_random(&self.request_defaults, 1)
+                .send()
+                .await?;
+
+            let value = random_value(&response);
+            let reflections = response.count(value);
+
+            let text = response.text.replace(value, "");
+            let first_text = first_text.get_or_insert_with(|| text.clone());
+
+            let reason = if response.code != self.initial_response.code {
+                format!("code {} -> {}", self.initial_response.code, response.code)
+            } else if similarity(first_text, &text) < WARM_UP_SIMILARITY {
+                format!("page {} -> {}", first_text.len(), text.len())
+            } else if reflections != self.request_defaults.amount_of_reflections {
+                format!(
+                    "reflections {} -> {}",
+                    self.request_defaults.amount_of_reflections, reflections
+                )
+            } else {
+                continue;
+            };
+
+            if !reasons.contains(&reason) {
+                reasons.push(reason);
+            }
+        }
+
+        if reasons.is_empty() {
+            return Ok(());
+        }
+
+        utils::info(
+            self.config,
+            self.id,
+            self.progress_bar,
+            "~",
+            format!(
+                "The baseline is unstable ({}), the results may be unreliable",
+                reasons.join(", ")
+            ),
+        );
+
+        if self.config.warm_up_confirm && self.confirm < WARM_UP_CONFIRM {
+            self.confirm = WARM_UP_CONFIRM;
+
+            utils::info(
+                self.config,
+                self.id,
+                self.progress_bar,
+                "~",
+                format!(
+                    "Every found parameter is going to be confirmed {} times",
+                    self.confirm
+                ),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// sends requests the same as the initial one and remembers the responses that differ from it,
+    /// so the legitimate variants of the page (like rotating A/B pages) aren't treated as changes.
+    /// fills self.baselines
+    async fn collect_baselines(&mut self) -> Result<(), Box<dyn Error>> {
+        for _ in 1..self.config.baselines {
+            let response = Request::new_random(&self.request_defaults, 1)
+                .send()
+                .await?;
+
+            if self.matches_baseline(&response)
+                || (response.code == self.initial_response.code
+                    && similarity(&self.initial_response.text, &response.text)
+                        >= self.config.baseline_similarity)
+            {
+                continue;
+            }
+
+            let text = response.text.replace(random_value(&response), "");
+
+            utils::info(
+                self.config,
+                self.id,
+                self.progress_bar,
+                "~",
+                format!(
+                    "Another variant of the page found (code {}, {} bytes)",
+                    response.code,
+                    text.len()
+                ),
+            );
+
+            self.baselines.push((response.code, text));
+        }
+
+        Ok(())
+    }
+
+    /// whether the response is similar to one of the page's variants from --baselines
+    pub fn matches_baseline(&self, response: &Response) -> bool {
+        self.baselines.iter().any(|(code, text)| {
+            *code == response.code
+                && similarity(text, &response.text) >= self.config.baseline_similarity
+        })
+    }
+
+    /// sends requests with a single random parameter and remembers how the page responds to them
+    /// fills self.calibration
+    async fn calibrate(&mut self) -> Result<(), Box<dyn Error>> {
+        for _ in 0..self.config.calibrate {
+            let response = Request::new_random(&self.request_defaults, 1)
+                .send()
+                .await?;
+
+            let request = response.request.as_ref().unwrap();
+            let reflections = request
+                .prepared_parameters
+                .iter()
+                .find(|(k, _)| !request.non_random_parameters.contains_key(k))
+                .map(|(_, v)| response.count(v))
+                .unwrap_or(0);
+
+            self.calibration
+                .add(response.code, response.text.len(), reflections);
+        }
+
         Ok(())
     }
 
@@ -326,6 +649,11 @@ impl<'a> Runner<'a> {
                 Err("The page's size > 25MB. Use --force flag to disable this error")?;
             }
 
+            // responses from another variant of the page would be treated as unstable
+            if self.matches_baseline(&response) {
+                continue;
+            }
+
             if !response.reflected_parameters.is_empty() {
                 stable.reflections = false;
             }
@@ -345,10 +673,11 @@ impl<'a> Runner<'a> {
             .await?;
 
         // in case the page is still different from other random ones - the body isn't stable
-        if !response
-            .compare(&self.initial_response, &diffs)?
-            .1
-            .is_empty()
+        if !self.matches_baseline(&response)
+            && !response
+                .compare(&self.initial_response, &diffs)?
+                .1
+                .is_empty()
         {
             utils::info(
                 self.config,
@@ -417,7 +746,10 @@ impl<'a> Runner<'a> {
             Ok(val) => val,
             // some servers may cut connection in case url is too long
             // that's why we assume that this request returned response with status code = 0.
-            Err(_) => Request::empty_response(Request::new_random(&self.request_defaults, 0)),
+            Err(err) => Request::empty_response(
+                Request::new_random(&self.request_defaults, 0),
+                Some(err.to_string()),
+            ),
         };
 
         loop {
@@ -446,13 +778,52 @@ impl<'a> Runner<'a> {
                 .await
             {
                 Ok(val) => val,
-                Err(_) => Request::empty_response(Request::new_random(&self.request_defaults, 0)),
+                Err(err) => Request::empty_response(
+                    Request::new_random(&self.request_defaults, 0),
+                    Some(err.to_string()),
+                ),
             };
         }
 
         Ok(max as isize * -1)
     }
 
+    /// halves the amount of headers per request while the server responds with 431 (Request Header Fields Too Large)
+    pub async fn try_to_guess_the_right_max_for_headers(
+        &mut self,
+    ) -> Result<isize, Box<dyn Error>> {
+        let mut max = 64;
+
+        while self.initial_response.code != 431
+            && Request::new_random(&self.request_defaults, max)
+                .send()
+                .await?
+                .code
+                == 431
+        {
+            max /= 2;
+
+            if max < 2 {
+                Err("The server doesn't accept additional headers (431). Try to use --max command line argument.")?
+            }
+        }
+
+        if max != 64 {
+            utils::info(
+                self.config,
+                self.id,
+                self.progress_bar,
+                "~",
+                format!(
+                    "The server limits the amount of headers. Reduced the amount of headers per request to {}",
+                    max
+                ),
+            );
+        }
+
+        Ok(-(max as isize))
+    }
+
     pub fn prepare_progress_bar(&self, sty: ProgressStyle, length: usize) {
         self.progress_bar.reset();
         self.progress_bar.set_prefix(self.make_progress_prefix());
@@ -514,3 +885,14 @@ impl<'a> Runner<'a> {
         }
     }
 }
+
+/// the value of the random parameter within the response's request
+fn random_value<'b>(response: &'b Response) -> &'b str {
+    let request = response.request.as_ref().unwrap();
+    request
+        .prepared_parameters
+        .iter()
+        .find(|(k, _)| !request.non_random_parameters.contains_key(k))
+        .map(|(_, v)| v.as_str())
+        .unwrap_or_default()
+}
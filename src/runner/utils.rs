@@ -1,17 +1,18 @@
-use std::error::Error;
+use std::{collections::HashMap, error::Error, time::Duration};
 
 use colored::Colorize;
 use lazy_static::lazy_static;
+use parking_lot::Mutex;
 use regex::Regex;
 use reqwest::Client;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     config::structs::Config,
     network::{
         request::{Request, RequestDefaults},
         response::Response,
-        utils::InjectionPlace,
+        utils::{save_request, InjectionPlace, ReflectionContext},
     },
     utils::random_line,
     VALUE_LENGTH,
@@ -23,15 +24,115 @@ pub struct Stable {
     pub reflections: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// how the page responds to random nonexistent parameters (--calibrate).
+/// Used to suppress findings on soft-404 and wildcard pages
+#[derive(Debug, Default)]
+pub struct Calibration {
+    pub codes: Vec<u16>,
+
+    /// min and max response sizes
+    pub size: (usize, usize),
+
+    /// min and max amount of reflections of the random value
+    pub reflections: (usize, usize),
+}
+
+impl Calibration {
+    pub fn add(&mut self, code: u16, size: usize, reflections: usize) {
+        if self.codes.is_empty() {
+            self.size = (size, size);
+            self.reflections = (reflections, reflections);
+        } else {
+            self.size = (self.size.0.min(size), self.size.1.max(size));
+            self.reflections = (
+                self.reflections.0.min(reflections),
+                self.reflections.1.max(reflections),
+            );
+        }
+
+        if !self.codes.contains(&code) {
+            self.codes.push(code);
+        }
+    }
+
+    /// whether the found parameter's response is indistinguishable from the responses to random parameters.
+    /// Reflection based findings are suppressed in case random parameters are reflected a different amount of times as well
+    pub fn matches(&self, found_param: &FoundParameter, amount_of_reflections: usize) -> bool {
+        if self.codes.is_empty() {
+            return false;
+        }
+
+        match found_param.reason_kind {
+            ReasonKind::Reflected | ReasonKind::NotReflected => {
+                self.reflections != (amount_of_reflections, amount_of_reflections)
+            }
+            _ => {
+                self.codes.contains(&found_param.status)
+                    && (self.size.0..=self.size.1).contains(&found_param.size)
+            }
+        }
+    }
+}
+
+/// response times of every request a parameter was sent within (--timing-report).
+/// Used to rank parameters by latency even if they are below the time detector's threshold
+#[derive(Debug, Default)]
+pub struct Timings {
+    samples: Mutex<HashMap<String, Vec<u128>>>,
+}
+
+impl Timings {
+    pub fn add(&self, params: &[String], time: u128) {
+        let mut samples = self.samples.lock();
+
+        for param in params {
+            samples.entry(param.to_owned()).or_default().push(time);
+        }
+    }
+
+    /// up to `limit` parameters with the highest median time, the difference with the baseline is returned as well.
+    /// Only parameters slower than the baseline are returned
+    pub fn slowest(&self, baseline: u128, limit: usize) -> Vec<(String, u128, u128)> {
+        let mut medians: Vec<(String, u128, u128)> = self
+            .samples
+            .lock()
+            .iter_mut()
+            .filter_map(|(param, times)| {
+                times.sort_unstable();
+                let median = times[times.len() / 2];
+
+                (median > baseline).then(|| (param.to_owned(), median, median - baseline))
+            })
+            .collect();
+
+        medians.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        medians.truncate(limit);
+
+        medians
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ReasonKind {
     Code,
     Text,
     Reflected,
     NotReflected,
+    /// the parameter's value is reflected within the Location header
+    LocationReflected,
+    /// found by a user supplied detector
+    Custom,
+    /// the parameter's name (not value) appears in the response, so the backend recognized it
+    Acknowledged,
+    /// the parameter changes the page differently over HTTP/1.1 and HTTP/2
+    Protocol,
+    /// the response matches the --interesting regex
+    Interesting,
+    /// the parameter changes the page only with the Cookie and Authorization headers
+    AuthGated,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FoundParameter {
     pub name: String,
 
@@ -42,6 +143,27 @@ pub struct FoundParameter {
     pub status: u16,
     pub size: usize,
     pub reason_kind: ReasonKind,
+
+    /// the sequential number of the response the parameter was found within (see Response::index)
+    #[serde(default)]
+    pub request_index: usize,
+
+    /// all the injection places the parameter was found within.
+    /// Filled only when findings are merged (--merge-findings)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub injection_places: Vec<InjectionPlace>,
+
+    /// where the value is reflected within the response. Filled only for reflected parameters
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reflection_contexts: Vec<ReflectionContext>,
+
+    /// how many times the parameter's value is reflected within the response
+    #[serde(skip)]
+    pub reflections: usize,
+
+    /// the request and the response the parameter was found with. Kept only for the markdown output
+    #[serde(skip)]
+    pub exchange: Option<String>,
 }
 
 impl FoundParameter {
@@ -51,6 +173,7 @@ impl FoundParameter {
         status: u16,
         size: usize,
         reason_kind: ReasonKind,
+        request_index: usize,
     ) -> Self {
         let name = name.into();
 
@@ -71,9 +194,32 @@ impl FoundParameter {
             status,
             size,
             reason_kind,
+            request_index,
+            injection_places: Vec::new(),
+            reflection_contexts: Vec::new(),
+            reflections: 0,
+            exchange: None,
         }
     }
 
+    /// remembers the details of the response the parameter was found within
+    pub fn with_response(mut self, config: &Config, response: &Response) -> Self {
+        if let Some((_, value)) = response.request.as_ref().and_then(|request| {
+            request
+                .prepared_parameters
+                .iter()
+                .find(|(k, _)| *k == self.name)
+        }) {
+            self.reflections = response.count(value);
+        }
+
+        if config.output_format == "markdown" {
+            self.exchange = Some(response.print_all());
+        }
+
+        self
+    }
+
     /// just returns (Key, Value) pair
     pub fn get(&self) -> (String, String) {
         (
@@ -91,12 +237,32 @@ impl FoundParameter {
             ReasonKind::Text => self.name.bright_yellow(),
             ReasonKind::Reflected => self.name.bright_blue(),
             ReasonKind::NotReflected => self.name.bright_cyan(),
+            ReasonKind::LocationReflected => self.name.bright_magenta(),
+            ReasonKind::Custom => self.name.bright_green(),
+            ReasonKind::Acknowledged => self.name.bright_white(),
+            ReasonKind::Protocol => self.name.bright_red(),
+            ReasonKind::Interesting => self.name.green(),
+            ReasonKind::AuthGated => self.name.purple(),
         };
 
-        if self.value.is_some() {
+        let param = if self.value.is_some() {
             format!("{}={}", param, self.value.as_ref().unwrap())
         } else {
             param.to_string()
+        };
+
+        if self.injection_places.len() > 1 {
+            format!(
+                "{} ({})",
+                param,
+                self.injection_places
+                    .iter()
+                    .map(|x| format!("{:?}", x))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+        } else {
+            param
         }
     }
 }
@@ -194,6 +360,12 @@ pub(super) async fn replay<'a>(
     replay_client: &Client,
     found_params: &Vec<FoundParameter>,
 ) -> Result<(), Box<dyn Error>> {
+    // the replayed requests aren't limited by --probe-timeout
+    let request_defaults = &RequestDefaults {
+        request_timeout: Duration::ZERO,
+        ..request_defaults.clone()
+    };
+
     // get cookies
     Request::new(request_defaults, vec![])
         .send_by(replay_client)
@@ -232,6 +404,12 @@ pub(super) async fn verify<'a>(
 ) -> Result<Vec<FoundParameter>, Box<dyn Error>> {
     let mut filtered_params = Vec::with_capacity(found_params.len());
 
+    // the verification requests aren't limited by --probe-timeout
+    let request_defaults = &RequestDefaults {
+        request_timeout: Duration::ZERO,
+        ..request_defaults.clone()
+    };
+
     for param in found_params {
         let param_value = param.get();
         let mut response = Request::new(
@@ -261,6 +439,48 @@ pub(super) async fn verify<'a>(
     Ok(filtered_params)
 }
 
+/// resends every found parameter alone (parameters from the config are kept) and
+/// saves the minimal request & response in case the difference is still present.
+/// Returns the locations of the saved files
+pub(super) async fn save_minimized<'a>(
+    config: &Config,
+    initial_response: &'a Response<'a>,
+    request_defaults: &'a RequestDefaults,
+    found_params: &[FoundParameter],
+    diffs: &[String],
+    stable: &Stable,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut filenames = Vec::with_capacity(found_params.len());
+
+    for param in found_params {
+        let param_value = param.get();
+        let mut response = Request::new(
+            request_defaults,
+            vec![format!("{}={}", param_value.0, param_value.1)],
+        )
+        .send()
+        .await?;
+
+        let (is_code_diff, new_diffs) = response.compare(initial_response, diffs)?;
+
+        response.fill_reflected_parameters(initial_response);
+
+        if is_code_diff
+            || !response.reflected_parameters.is_empty()
+            || stable.body && !new_diffs.is_empty()
+        {
+            filenames.push(save_request(
+                config,
+                &response,
+                initial_response,
+                &format!("{}-minimal", param.name),
+            )?);
+        }
+    }
+
+    Ok(filenames)
+}
+
 pub enum ParamPatterns {
     /// _anything
     SpecialPrefix(char),
@@ -1,4 +1,5 @@
 use std::{
+    error::Error,
     fs::File,
     io::{self, BufRead, Write},
     path::Path,
@@ -12,6 +13,7 @@ use url::Url;
 
 use crate::{
     config::{structs::Config, utils::file_writer},
+    network::utils::create_client,
     RANDOM_CHARSET,
 };
 
@@ -167,6 +169,52 @@ where
     Ok(io::BufReader::new(file).lines())
 }
 
+/// fetch wordlist with parameters over http(s)
+/// uses the same client settings (proxy, timeout, ..) as the main requests
+pub async fn read_remote_lines(config: &Config, url: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let response = create_client(config, false)?.get(url).send().await?;
+
+    if !response.status().is_success() {
+        Err(format!(
+            "Unable to fetch the wordlist ({})",
+            response.status().as_u16()
+        ))?;
+    }
+
+    Ok(response
+        .text()
+        .await?
+        .lines()
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect())
+}
+
+/// strips the optional weights (`name<TAB>weight`) and orders the parameters by weight descending,
+/// so the most probable parameters are checked first. Parameters without weights have the weight 0.
+/// Ties keep the wordlist order
+pub fn order_by_weight(params: Vec<String>) -> Vec<String> {
+    let mut weighted: Vec<(String, f64)> = params
+        .into_iter()
+        .map(|x| {
+            let weight = x
+                .rsplit_once('\t')
+                .and_then(|(name, weight)| Some((name, weight.trim().parse::<f64>().ok()?)))
+                .filter(|(_, weight)| weight.is_finite());
+
+            match weight {
+                Some((name, weight)) => (name.to_string(), weight),
+                None => (x, 0.),
+            }
+        })
+        .collect();
+
+    // sort_by is stable
+    weighted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    weighted.into_iter().map(|(x, _)| x).collect()
+}
+
 /// read parameters from stdin
 pub fn read_stdin_lines() -> Vec<String> {
     let stdin = io::stdin();